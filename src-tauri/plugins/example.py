@@ -0,0 +1,72 @@
+#!/usr/bin/env python3
+# An example plugin, copy it into the plugins directory next to config.json
+# (e.g. ~/.config/swordfish/plugins on Linux) and make it executable to try it out.
+#
+# Plugins talk JSON-RPC 2.0 over stdin/stdout, one message per line. Anything else
+# printed to stdout or stderr ends up in swordfish's log.
+
+import json
+import sys
+import webbrowser
+
+PAGES = [
+    ("Onboarding", "https://example.com/wiki/onboarding"),
+    ("Release process", "https://example.com/wiki/releases"),
+    ("On-call handbook", "https://example.com/wiki/on-call"),
+]
+
+
+def initialize(params):
+    return {"name": "Example wiki", "modes": ["Search"]}
+
+
+def query(params):
+    term = params["query"]["search_string"].lower()
+    if not term:
+        return []
+    return [
+        {
+            "heading": title,
+            "subheading": url,
+            "value": url,
+            "description": f"Wiki page '{title}'",
+        }
+        for title, url in PAGES
+        if term in title.lower()
+    ]
+
+
+def execute_action(params):
+    webbrowser.open(params["value"])
+    return None
+
+
+METHODS = {
+    "initialize": initialize,
+    "query": query,
+    "execute_action": execute_action,
+}
+
+
+def main():
+    # stdin is closed when swordfish quits
+    for line in sys.stdin:
+        if not line.strip():
+            continue
+        request = json.loads(line)
+        # notifications like `cancel` don't get an answer, this plugin answers fast
+        # enough to not need them
+        if "id" not in request:
+            continue
+        method = METHODS.get(request.get("method"))
+        if method is None:
+            response = {"code": -32601, "message": f"unknown method {request.get('method')}"}
+            response = {"jsonrpc": "2.0", "id": request.get("id"), "error": response}
+        else:
+            result = method(request.get("params") or {})
+            response = {"jsonrpc": "2.0", "id": request.get("id"), "result": result}
+        print(json.dumps(response), flush=True)
+
+
+if __name__ == "__main__":
+    main()
@@ -1,5 +1,5 @@
 use crate::{
-    settings::AppConfig,
+    settings::{AppConfig, ConfigError},
     windows::{acquire_main_window, hide_main_window},
 };
 use axum::{
@@ -15,12 +15,19 @@ use tower::ServiceBuilder;
 
 pub struct AppState {
     pub config: Mutex<AppConfig>,
+    /* problems found while loading config.json, shown in the settings window */
+    pub config_errors: Mutex<Vec<ConfigError>>,
 }
 
 impl AppState {
     pub fn new() -> Self {
+        let settings = AppConfig::load();
+        for error in settings.errors.iter() {
+            eprintln!("Config error in '{}': {}", error.field, error.message);
+        }
         Self {
-            config: Mutex::new(AppConfig::new()),
+            config: Mutex::new(settings.config),
+            config_errors: Mutex::new(settings.errors),
         }
     }
 }
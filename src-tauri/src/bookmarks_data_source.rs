@@ -0,0 +1,370 @@
+use crate::browser_data_source::{copy_browser_sqlite_to_tmpdir, Browser};
+use crate::history_privacy::HistoryPrivacy;
+use crate::history_reader::{history_readers, HistoryProfile};
+use crate::settings::{AppConfig, BrowserHistoryConfig};
+use crate::sqlite::SQLite;
+use fuzzy_matcher::skim::SkimMatcherV2;
+use fuzzy_matcher::FuzzyMatcher;
+use rayon::prelude::*;
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+use std::{cmp, fs, thread};
+use swordfish_types::{DataSource, Query};
+
+// how often the bookmark files are checked for changes, they're only read again when
+// one of them or the privacy settings changed
+const REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+
+//* A bookmark saved in a browser */
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Bookmark {
+    pub browser: Browser,
+    pub profile: String,
+    pub url: String,
+    pub title: String,
+    /* slash separated folder names, e.g. "Bookmarks Bar/Work" */
+    pub folder: String,
+}
+
+impl Bookmark {
+    pub fn source_label(&self) -> String {
+        self.browser.source_label(&self.profile)
+    }
+}
+
+fn join_folder(parent: &str, name: &str) -> String {
+    if parent.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}/{}", parent, name)
+    }
+}
+
+fn collect_chromium_bookmarks(
+    node: &Value,
+    folder: &str,
+    profile: &HistoryProfile,
+    browser: Browser,
+    bookmarks: &mut Vec<Bookmark>,
+) {
+    let name = node.get("name").and_then(Value::as_str).unwrap_or("");
+    match node.get("type").and_then(Value::as_str) {
+        Some("url") => {
+            if let Some(url) = node.get("url").and_then(Value::as_str) {
+                bookmarks.push(Bookmark {
+                    browser,
+                    profile: profile.name.clone(),
+                    url: url.to_string(),
+                    title: name.to_string(),
+                    folder: folder.to_string(),
+                });
+            }
+        }
+        Some("folder") => {
+            let folder = join_folder(folder, name);
+            if let Some(children) = node.get("children").and_then(Value::as_array) {
+                for child in children {
+                    collect_chromium_bookmarks(child, &folder, profile, browser, bookmarks);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+// Chromium keeps bookmarks in a JSON file named `Bookmarks` next to the profile's history
+fn read_chromium_bookmarks(browser: Browser, profile: &HistoryProfile) -> Vec<Bookmark> {
+    let bookmarks_path = match profile.history_path.parent() {
+        Some(dir) => dir.join("Bookmarks"),
+        None => return vec![],
+    };
+    let contents = match fs::read_to_string(&bookmarks_path) {
+        Ok(contents) => contents,
+        Err(_) => return vec![],
+    };
+
+    let mut bookmarks = vec![];
+    match serde_json::from_str::<Value>(&contents) {
+        Ok(json) => {
+            if let Some(roots) = json.get("roots").and_then(Value::as_object) {
+                for root in roots.values() {
+                    collect_chromium_bookmarks(root, "", profile, browser, &mut bookmarks);
+                }
+            }
+        }
+        Err(e) => eprintln!("Error parsing {:?}: {:?}", bookmarks_path, e),
+    }
+    bookmarks
+}
+
+// Folder paths are built by walking up from the root folders. The tags root is
+// skipped because every tagged bookmark is duplicated underneath it.
+const FIREFOX_BOOKMARKS_QUERY: &str = r#"WITH RECURSIVE folders(id, path) AS (
+        SELECT id, CASE guid
+            WHEN 'menu________' THEN 'Bookmarks Menu'
+            WHEN 'toolbar_____' THEN 'Bookmarks Toolbar'
+            WHEN 'unfiled_____' THEN 'Other Bookmarks'
+            WHEN 'mobile______' THEN 'Mobile Bookmarks'
+            ELSE COALESCE(title, '')
+        END
+        FROM moz_bookmarks
+        WHERE parent = (SELECT id FROM moz_bookmarks WHERE guid = 'root________')
+            AND guid != 'tags________'
+        UNION ALL
+        SELECT b.id, f.path || '/' || COALESCE(b.title, '')
+        FROM moz_bookmarks b
+        JOIN folders f ON b.parent = f.id
+        WHERE b.type = 2
+    )
+    SELECT p.url, COALESCE(b.title, p.title, ''), f.path
+    FROM moz_bookmarks b
+    JOIN moz_places p ON p.id = b.fk
+    JOIN folders f ON f.id = b.parent
+    WHERE b.type = 1"#;
+
+fn read_firefox_bookmarks(
+    conn: &Connection,
+    profile: &HistoryProfile,
+) -> rusqlite::Result<Vec<Bookmark>> {
+    let mut statement = conn.prepare(FIREFOX_BOOKMARKS_QUERY)?;
+    let bookmarks = statement
+        .query_map([], |row| {
+            Ok(Bookmark {
+                browser: Browser::Firefox,
+                profile: profile.name.clone(),
+                url: row.get(0)?,
+                title: row.get(1)?,
+                folder: row.get(2)?,
+            })
+        })?
+        .filter_map(Result::ok)
+        .collect();
+    Ok(bookmarks)
+}
+
+// disabled browsers and denied domains apply to bookmarks like they do to the history
+fn read_browser_bookmarks(home: &Path, privacy: &HistoryPrivacy) -> Vec<Bookmark> {
+    history_readers()
+        .par_iter()
+        .filter(|reader| privacy.is_browser_enabled(reader.browser()))
+        .flat_map(|reader| {
+            let browser = reader.browser();
+            reader
+                .profiles(home)
+                .iter()
+                .enumerate()
+                .flat_map(|(i, profile)| match browser {
+                    // Safari bookmarks live in a plist and are not supported yet
+                    Browser::Safari => vec![],
+                    Browser::Firefox => {
+                        let copy_name = format!("bookmarks-{}-{}", browser.to_str(), i + 1);
+                        copy_browser_sqlite_to_tmpdir(&profile.history_path, &copy_name)
+                            .and_then(|path| SQLite::from_path(&path.to_string_lossy(), true).ok())
+                            .and_then(|sqlite| {
+                                read_firefox_bookmarks(&sqlite.conn, profile)
+                                    .map_err(|e| {
+                                        eprintln!("Error reading Firefox bookmarks: {:?}", e);
+                                    })
+                                    .ok()
+                            })
+                            .unwrap_or_default()
+                    }
+                    chromium => read_chromium_bookmarks(chromium, profile),
+                })
+                .filter(|bookmark| !privacy.denies_url(&bookmark.url))
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+type BookmarkFiles = Vec<(PathBuf, Option<SystemTime>)>;
+
+// the files every browser keeps its bookmarks in, with when they were last modified
+fn bookmark_files_modified(home: &Path) -> BookmarkFiles {
+    history_readers()
+        .iter()
+        .flat_map(|reader| {
+            let browser = reader.browser();
+            reader
+                .profiles(home)
+                .into_iter()
+                .filter_map(move |profile| match browser {
+                    Browser::Safari => None,
+                    // Firefox writes to the write-ahead log first
+                    Browser::Firefox => {
+                        let mut wal_path = profile.history_path.clone().into_os_string();
+                        wal_path.push("-wal");
+                        Some(vec![profile.history_path, PathBuf::from(wal_path)])
+                    }
+                    _ => profile
+                        .history_path
+                        .parent()
+                        .map(|dir| vec![dir.join("Bookmarks")]),
+                })
+                .flatten()
+                .collect::<Vec<_>>()
+        })
+        .map(|path| {
+            let modified = fs::metadata(&path).and_then(|m| m.modified()).ok();
+            (path, modified)
+        })
+        .collect()
+}
+
+pub struct BookmarksDataSource {
+    name: String,
+    sqlite: SQLite,
+}
+
+impl BookmarksDataSource {
+    //* Keeps the bookmarks table up to date on this connection until the app exits */
+    pub fn start_background_refresh(mut self) {
+        thread::spawn(move || {
+            let mut indexed: Option<(BrowserHistoryConfig, BookmarkFiles)> = None;
+            loop {
+                if let Some(home) = dirs::home_dir() {
+                    let current = (
+                        AppConfig::read().unwrap_or_default().browser_history,
+                        bookmark_files_modified(&home),
+                    );
+                    if indexed.as_ref() != Some(&current) {
+                        self.update_cache();
+                        indexed = Some(current);
+                    }
+                }
+                thread::sleep(REFRESH_INTERVAL);
+            }
+        });
+    }
+
+    pub fn read(&self) -> Option<Vec<Bookmark>> {
+        let query_statement = format!("SELECT * FROM {}", self.name);
+
+        self.sqlite
+            .conn
+            .prepare(&query_statement)
+            .map_err(|e| {
+                println!("Error reading from bookmarks DB: {}", e);
+            })
+            .ok()
+            .and_then(|mut statement| {
+                statement
+                    .query_map([], |row| {
+                        Ok(Bookmark {
+                            browser: Browser::from_string(row.get("browser")?),
+                            profile: row.get("profile")?,
+                            url: row.get("url")?,
+                            title: row.get("title")?,
+                            folder: row.get("folder")?,
+                        })
+                    })
+                    .ok()
+                    .map(|entries| entries.filter_map(Result::ok).collect::<Vec<_>>())
+            })
+    }
+}
+
+impl DataSource<Vec<Bookmark>> for BookmarksDataSource {
+    fn new(name: &str) -> Self {
+        if let Ok(sqlite) = SQLite::new(name, false) {
+            let transaction = format!(
+                "CREATE TABLE IF NOT EXISTS {} (
+                  browser TEXT NOT NULL,
+                  profile TEXT NOT NULL,
+                  url TEXT NOT NULL,
+                  title TEXT NOT NULL,
+                  folder TEXT NOT NULL,
+                  UNIQUE (browser, profile, url, folder)
+                )",
+                name
+            );
+            if let Err(e) = sqlite.conn.execute(&transaction, []) {
+                eprintln!(
+                    "failed to create the table '{}', maybe it already exists?\n{:?}",
+                    name, e
+                )
+            };
+            Self {
+                sqlite,
+                name: name.to_string(),
+            }
+        } else {
+            panic!("Error initializing the BookmarksDataSource")
+        }
+    }
+
+    fn update_cache(&mut self) {
+        let home = match dirs::home_dir() {
+            Some(home) => home,
+            None => {
+                eprintln!("Unable to locate the home directory, skipping bookmarks");
+                return;
+            }
+        };
+        let bookmarks = read_browser_bookmarks(&home, &HistoryPrivacy::current());
+
+        // bookmarks are small enough that a complete reindex is cheaper than diffing
+        let result: Result<(), rusqlite::Error> = (|| {
+            let transaction = self.sqlite.conn.transaction()?;
+            transaction.execute(&format!("DELETE FROM {}", self.name), [])?;
+            {
+                let mut statement = transaction.prepare(&format!(
+                    "INSERT OR IGNORE INTO {} (browser, profile, url, title, folder) VALUES (?1, ?2, ?3, ?4, ?5)",
+                    self.name
+                ))?;
+                for bookmark in bookmarks.iter() {
+                    statement.execute(params![
+                        bookmark.browser.to_str(),
+                        bookmark.profile,
+                        bookmark.url,
+                        bookmark.title,
+                        bookmark.folder
+                    ])?;
+                }
+            }
+            transaction.commit()
+        })();
+
+        match result {
+            Ok(_) => println!("Finished caching {} bookmarks", bookmarks.len()),
+            Err(e) => eprintln!("Error writing bookmarks to the cache: {:?}", e),
+        }
+    }
+
+    fn query(&self, query: &Query) -> Option<Vec<Bookmark>> {
+        self.read().map(|bookmarks| {
+            let search_string = query.search_string.clone();
+            let start = Instant::now();
+
+            let matcher = SkimMatcherV2::default().ignore_case();
+            let mut scored_bookmarks: Vec<(i64, Bookmark)> = bookmarks
+                .par_iter()
+                .filter_map(|bookmark| {
+                    let score = [&bookmark.title, &bookmark.url, &bookmark.folder]
+                        .iter()
+                        .filter_map(|field| matcher.fuzzy_match(field, &search_string))
+                        .fold(0, cmp::max);
+                    if score > 0 {
+                        Some((score, bookmark.to_owned()))
+                    } else {
+                        None
+                    }
+                })
+                .collect();
+            println!(
+                "finished bookmarks search in {}ms",
+                start.elapsed().as_millis()
+            );
+
+            scored_bookmarks.sort_by(|a, b| b.0.cmp(&a.0));
+            scored_bookmarks
+                .into_iter()
+                .take(50)
+                .map(|(_, bookmark)| bookmark)
+                .collect()
+        })
+    }
+}
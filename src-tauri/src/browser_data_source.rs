@@ -1,40 +1,42 @@
+use crate::frecency::{calculate_frecency, Visit, VisitKind, MAX_SAMPLED_VISITS};
+use crate::history_privacy::{DomainPattern, DomainPatternError, HistoryPrivacy};
+use crate::history_reader::{history_readers, HistoryProfile, HistoryReader};
+use crate::settings::AppConfig;
 use crate::sqlite::SQLite;
+use crate::url_normalization::normalize_url;
 use chrono::Local;
 use fuzzy_matcher::skim::SkimMatcherV2;
-use glob::glob;
 use rayon::prelude::*;
 use rusqlite::params;
 use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 use std::io::Write;
-use std::path::PathBuf;
-use std::time::Instant;
-use std::{cmp, env, fs};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, UNIX_EPOCH};
+use std::{cmp, env, fs, thread};
 use swordfish_types::{DataSource, Query};
 use swordfish_utilities::get_favicon_cache_path;
 use thiserror::Error;
+use tokio::runtime::Handle;
 use tokio::spawn;
+use ts_rs::TS;
 use url::Url;
 
-fn calculate_frecency(history: &HistoryEntry) -> f64 {
-    let current_time = Local::now();
-    let timestamp = current_time.timestamp();
-    let visit_weight = 0.40;
-    let age_weight = 0.60;
-    if history.last_visit_time > timestamp {
-        println!("Invalid timestamp {:?}", &history);
-        return 0.0;
-    }
-    let age = (timestamp - history.last_visit_time) / (60 * 60 * 24);
+const HISTORY_SYNC_INTERVAL: Duration = Duration::from_secs(5 * 60);
+// a sync writes everything it read in one transaction, which can take longer than
+// the connection's usual busy timeout
+const FORGET_BUSY_TIMEOUT: Duration = Duration::from_secs(60);
 
-    let score = (age as f64 * age_weight) + (history.visit_count as f64 * visit_weight);
+// how much a page's frecency can multiply its fuzzy match score
+const FRECENCY_WEIGHT: f64 = 0.25;
 
-    if score <= 0.0 {
-        return 0.0;
-    }
-    score
+//* Ranks a fuzzy match higher the more frecent the page is, without letting frecency alone produce a match */
+fn blended_score(fuzzy_score: i64, frecency_score: f64) -> f64 {
+    fuzzy_score as f64 * (1.0 + FRECENCY_WEIGHT * frecency_score.max(0.0).ln_1p())
 }
 
-#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, PartialOrd, Clone, Copy)]
+#[derive(TS, Serialize, Deserialize, Debug, PartialEq, Eq, PartialOrd, Clone, Copy)]
+#[ts(export, export_to = "../../src/types/")]
 pub enum Browser {
     Arc,
     Chrome,
@@ -94,13 +96,27 @@ impl Browser {
     pub fn from_string(str: String) -> Self {
         Browser::from_str(str.as_str())
     }
+
+    //* e.g. "Chrome — Work", or just "Safari" when the profile has no name */
+    pub fn source_label(&self, profile: &str) -> String {
+        if profile.is_empty() {
+            self.to_str().to_string()
+        } else {
+            format!("{} — {}", self.to_str(), profile)
+        }
+    }
 }
 
 //* An entry from browser history */
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct HistoryEntry {
     pub browser: Browser,
+    /* display name of the browser profile, empty when the browser has no named profiles */
+    pub profile: String,
+    /* normalized form used to tell pages apart, see url_normalization */
     pub url: String,
+    /* the url as the browser last visited it, used for opening the page */
+    pub original_url: String,
     pub title: String,
     pub visit_count: i64,
     /* timestamp in seconds */
@@ -108,195 +124,226 @@ pub struct HistoryEntry {
     pub frecency_score: f64,
 }
 
-//* Definition of a single browser history sqlite location and how to get the data we need out */
-#[derive(Debug, Clone)]
-pub struct HistorySchema {
-    pub browser: Browser,
-    pub path: String,
-    pub query: String,
-}
-struct BrowserPaths {
-    arc: Option<PathBuf>,
-    chrome: Option<PathBuf>,
-    firefox: Option<PathBuf>,
-    safari: Option<PathBuf>,
-    opera: Option<PathBuf>,
-    brave: Option<PathBuf>,
-    edge: Option<PathBuf>,
-    vivaldi: Option<PathBuf>,
-    chromium: Option<PathBuf>,
-}
-
-impl BrowserPaths {
-    fn new() -> Self {
-        let home = dirs::home_dir();
-
-        BrowserPaths {
-            arc: home.as_ref().and_then(Self::arc_path),
-            chrome: home.as_ref().and_then(Self::chrome_path),
-            firefox: home.as_ref().and_then(Self::firefox_path),
-            safari: home.as_ref().and_then(Self::safari_path),
-            opera: home.as_ref().and_then(Self::opera_path),
-            brave: home.as_ref().and_then(Self::brave_path),
-            edge: home.as_ref().and_then(Self::edge_path),
-            vivaldi: home.as_ref().and_then(Self::vivaldi_path),
-            chromium: home.as_ref().and_then(Self::chromium_path),
-        }
-    }
-
-    fn arc_path(home: &PathBuf) -> Option<PathBuf> {
-        match std::env::consts::OS {
-            "macos" => Some(home.join("Library/Application Support/Arc/User Data/Default/History")),
-            "windows" => Some(home.join("AppData/Local/Arc/User Data/Default/History")),
-            "linux" => Some(home.join(".config/Arc/User Data/Default/History")),
-            _ => None,
-        }
-    }
-
-    fn chrome_path(home: &PathBuf) -> Option<PathBuf> {
-        match std::env::consts::OS {
-            "macos" => Some(home.join("Library/Application Support/Google/Chrome/Default/History")),
-            "windows" => Some(home.join("AppData/Local/Google/Chrome/User Data/Default/History")),
-            "linux" => Some(home.join(".config/google-chrome/Default/History")),
-            _ => None,
-        }
-    }
-
-    fn firefox_path(home: &PathBuf) -> Option<PathBuf> {
-        match std::env::consts::OS {
-            "macos" => {
-                Some(home.join("Library/Application Support/Firefox/Profiles/**/places.sqlite"))
-            }
-            "windows" => {
-                Some(home.join(
-                    "AppData/Roaming/Mozilla/Firefox/Profiles/*.default-release/places.sqlite",
-                ))
-            }
-            "linux" => Some(home.join(".mozilla/firefox/*.default-release/places.sqlite")),
-            _ => None,
-        }
-    }
-
-    fn safari_path(home: &PathBuf) -> Option<PathBuf> {
-        if std::env::consts::OS == "macos" {
-            Some(home.join("Library/Safari/History.db"))
-        } else {
-            None
-        }
+impl HistoryEntry {
+    pub fn source_label(&self) -> String {
+        self.browser.source_label(&self.profile)
     }
+}
 
-    fn opera_path(home: &PathBuf) -> Option<PathBuf> {
-        match std::env::consts::OS {
-            "macos" => {
-                Some(home.join("Library/Application Support/com.operasoftware.Opera/History"))
+// several urls can normalize to the same page. They're merged by the same rules as
+// the upsert merges them with what's already stored: the highest visit count, since
+// the same entry is read again whenever a profile is resynced and a sum would count
+// its visits twice, and the most recently visited url for opening.
+fn merge_duplicate_entries(entries: Vec<HistoryEntry>) -> Vec<HistoryEntry> {
+    let mut merged: HashMap<String, HistoryEntry> = HashMap::new();
+    for entry in entries {
+        match merged.get_mut(&entry.url) {
+            Some(existing) => {
+                existing.visit_count = cmp::max(existing.visit_count, entry.visit_count);
+                let newer = entry.last_visit_time >= existing.last_visit_time;
+                if !entry.title.is_empty() && (newer || existing.title.is_empty()) {
+                    existing.title = entry.title;
+                }
+                if newer {
+                    existing.browser = entry.browser;
+                    existing.profile = entry.profile;
+                    existing.original_url = entry.original_url;
+                    existing.last_visit_time = entry.last_visit_time;
+                }
             }
-            "windows" => Some(home.join("AppData/Roaming/Opera Software/Opera Stable/History")),
-            "linux" => Some(home.join(".config/opera/History")),
-            _ => None,
-        }
-    }
-
-    fn brave_path(home: &PathBuf) -> Option<PathBuf> {
-        match std::env::consts::OS {
-            "macos" => {
-                Some(home.join(
-                    "Library/Application Support/BraveSoftware/Brave-Browser/Default/History",
-                ))
+            None => {
+                merged.insert(entry.url.clone(), entry);
             }
-            "windows" => Some(
-                home.join("AppData/Local/BraveSoftware/Brave-Browser/User Data/Default/History"),
-            ),
-            "linux" => Some(home.join(".config/BraveSoftware/Brave-Browser/Default/History")),
-            _ => None,
         }
     }
+    merged.into_values().collect()
+}
 
-    fn edge_path(home: &PathBuf) -> Option<PathBuf> {
-        match std::env::consts::OS {
-            "macos" => {
-                Some(home.join("Library/Application Support/Microsoft Edge/Default/History"))
-            }
-            "windows" => Some(home.join("AppData/Local/Microsoft/Edge/User Data/Default/History")),
-            "linux" => Some(home.join(".config/microsoft-edge/Default/History")),
-            _ => None,
-        }
+// if a browser is running you cannot read the history sqlite directly
+// because it's locked. You have to copy it somewhere else and use that copy instead
+pub fn copy_browser_sqlite_to_tmpdir(from: &Path, name: &str) -> Option<PathBuf> {
+    let mut dest_path = env::temp_dir();
+    dest_path.push(format!("sf-history-{}.sqlite", name));
+    if let Err(e) = fs::copy(from, &dest_path) {
+        eprintln!("Error: {:?}", e);
+        return None;
     }
 
-    fn vivaldi_path(home: &PathBuf) -> Option<PathBuf> {
-        match std::env::consts::OS {
-            "macos" => Some(home.join("Library/Application Support/Vivaldi/Default/History")),
-            "windows" => Some(home.join("AppData/Local/Vivaldi/User Data/Default/History")),
-            "linux" => Some(home.join(".config/vivaldi/Default/History")),
-            _ => None,
+    // recent visits may only exist in the write-ahead log until the browser checkpoints it
+    let mut wal_path = from.as_os_str().to_owned();
+    wal_path.push("-wal");
+    let mut dest_wal_path = dest_path.as_os_str().to_owned();
+    dest_wal_path.push("-wal");
+    if Path::new(&wal_path).is_file() {
+        if let Err(e) = fs::copy(&wal_path, &dest_wal_path) {
+            eprintln!("Error copying the history write-ahead log: {:?}", e);
         }
+    } else {
+        fs::remove_file(&dest_wal_path).ok();
     }
 
-    fn chromium_path(home: &PathBuf) -> Option<PathBuf> {
-        match std::env::consts::OS {
-            "macos" => Some(home.join("Library/Application Support/Chromium/Default/History")),
-            "windows" => Some(home.join("AppData/Local/Chromium/User Data/Default/History")),
-            "linux" => Some(home.join(".config/chromium/Default/History")),
-            _ => None,
-        }
-    }
+    println!("Copied to {:?}", dest_path);
+    Some(dest_path)
 }
 
-const DEFAULT_QUERY: &str = "SELECT id, url, title, visit_count, CAST(((CAST(last_visit_time as REAL) - 11644473600000000) / 1000000) as BIGINT) as last_visit_time FROM urls ORDER BY visit_count DESC";
+//* How far a browser profile's history has already been synced */
+#[derive(Debug, Clone, Default)]
+struct SyncState {
+    /* newest visit already synced, in unix seconds */
+    last_visit_time: i64,
+    /* modification time of the browser's database (or its write-ahead log) when it was last read */
+    source_modified: i64,
+}
 
-pub fn get_history_schemas() -> Vec<HistorySchema> {
-    let browser_paths = BrowserPaths::new();
+//* The new and changed entries of one browser profile since its last sync */
+struct ProfileSync {
+    key: String,
+    entries: Vec<HistoryEntry>,
+    visits: Vec<(String, Visit)>,
+    state: SyncState,
+    /* icons from the browser's own favicon database for domains that aren't cached yet */
+    favicons: HashMap<String, Vec<u8>>,
+}
 
-    let possible_browser_histories = vec![
-        (browser_paths.chrome, DEFAULT_QUERY, Browser::Chrome),
-        (browser_paths.arc, DEFAULT_QUERY, Browser::Arc),
-        (browser_paths.brave, DEFAULT_QUERY, Browser::Brave),
-        (browser_paths.firefox, "SELECT id, url, COALESCE(title, \"\"), visit_count, COALESCE(CAST(last_visit_date / 1000000) as BIGINT), 0) FROM moz_places ORDER BY visit_count DESC", Browser::Firefox),
-        (browser_paths.safari, "SELECT i.id, i.url, COALESCE(v.title, \"\"), i.visit_count, CAST(v.visit_time + 978307200 as BIGINT) as visit_time FROM history_items i LEFT JOIN history_visits v ON i.id = v.history_item ORDER BY i.visit_count DESC", Browser::Safari)
-    ];
+fn favicon_cache_file(domain: &str) -> Option<PathBuf> {
+    get_favicon_cache_path().map(|mut path| {
+        path.push(format!("{}.png", domain));
+        path
+    })
+}
 
-    possible_browser_histories
+fn uncached_favicon_domains(entries: &[HistoryEntry]) -> HashSet<String> {
+    entries
         .iter()
-        .filter_map(|(path, query, browser)| {
-            if let Some(p) = path {
-                if p.exists() {
-                    Some(HistorySchema {
-                        browser: browser.to_owned(),
-                        path: p.to_string_lossy().to_string(),
-                        query: query.to_owned().to_owned(),
-                    })
-                } else {
-                    None
-                }
-            } else {
-                None
-            }
-        })
+        .filter_map(|entry| Url::parse(&entry.url).ok())
+        .filter_map(|url| url.domain().map(str::to_string))
+        .filter(|domain| favicon_cache_file(domain).map_or(false, |path| !path.exists()))
         .collect()
 }
 
-// if a browser is running you cannot read the history sqlite directly
-// because it's locked. You have to copy it somewhere else and use that copy instead
-fn copy_browser_sqlite_to_tmpdir(from: &str, name: &str) {
-    let mut dest_path = env::temp_dir();
-    dest_path.push(format!("sf-history-{}.sqlite", name));
-    if let Err(e) = fs::copy(from, &dest_path) {
-        eprintln!("Error: {:?}", e);
-    } else {
-        println!("Copied to {:?}", dest_path);
+fn read_browser_favicons(
+    reader: &dyn HistoryReader,
+    profile: &HistoryProfile,
+    copy_name: &str,
+    domains: &HashSet<String>,
+) -> HashMap<String, Vec<u8>> {
+    if domains.is_empty() {
+        return HashMap::new();
+    }
+    reader
+        .favicons_path(profile)
+        .filter(|path| path.is_file())
+        .and_then(|path| copy_browser_sqlite_to_tmpdir(&path, &format!("favicons-{}", copy_name)))
+        .and_then(|path| SQLite::from_path(&path.to_string_lossy(), true).ok())
+        .and_then(|sqlite| {
+            reader
+                .read_favicons(&sqlite.conn, domains)
+                .map_err(|e| {
+                    eprintln!(
+                        "Error reading {} favicons for profile '{}': {:?}",
+                        reader.browser().to_str(),
+                        profile.name,
+                        e
+                    );
+                })
+                .ok()
+        })
+        .unwrap_or_default()
+}
+
+fn write_favicons(favicons: &HashMap<String, Vec<u8>>) {
+    for (domain, image) in favicons {
+        if let Some(path) = favicon_cache_file(domain) {
+            if let Err(e) = fs::write(&path, image) {
+                eprintln!("Error writing favicon for '{}': {:?}", domain, e);
+            }
+        }
     }
 }
 
-fn get_copied_sqlite_paths_for_history_schema(schema: &HistorySchema) -> Vec<String> {
-    let pattern = format!("sf-history-{}*.sqlite", schema.browser.to_str());
-    let mut tmp_path = env::temp_dir();
-    tmp_path.push(pattern);
+fn source_modified_time(history_path: &Path) -> i64 {
+    let mut wal_path = history_path.as_os_str().to_owned();
+    wal_path.push("-wal");
+    [history_path, Path::new(&wal_path)]
+        .iter()
+        .filter_map(|path| fs::metadata(path).and_then(|m| m.modified()).ok())
+        .filter_map(|modified| modified.duration_since(UNIX_EPOCH).ok())
+        .map(|since_epoch| since_epoch.as_secs() as i64)
+        .max()
+        .unwrap_or(0)
+}
 
-    let glob_pattern = tmp_path.to_str().unwrap_or_else(|| panic!("Invalid path"));
+fn read_browser_history(
+    reader: &dyn HistoryReader,
+    home: &Path,
+    sync_states: &HashMap<String, SyncState>,
+) -> Vec<ProfileSync> {
+    reader
+        .profiles(home)
+        .iter()
+        .enumerate()
+        .filter_map(|(i, profile)| {
+            let key = profile.history_path.to_string_lossy().to_string();
+            let previous = sync_states.get(&key).cloned().unwrap_or_default();
+            let source_modified = source_modified_time(&profile.history_path);
+            // nothing was visited since the last sync, skip copying the database
+            if source_modified != 0 && source_modified == previous.source_modified {
+                return None;
+            }
 
-    glob(glob_pattern)
-        .expect("error finding sqlite globs in tmp dir")
-        .filter_map(Result::ok)
-        .map(|path| path.to_string_lossy().to_string())
+            let copy_name = format!("{}-{}", reader.browser().to_str(), i + 1);
+            let path = copy_browser_sqlite_to_tmpdir(&profile.history_path, &copy_name)?;
+            let sqlite = SQLite::from_path(&path.to_string_lossy(), true)
+                .map_err(|e| {
+                    eprintln!("Error connecting to db: {:?}", e);
+                })
+                .ok()?;
+            let entries = reader
+                .read(&sqlite.conn, profile, previous.last_visit_time)
+                .map_err(|e| {
+                    eprintln!(
+                        "Error reading {} history for profile '{}': {:?}",
+                        reader.browser().to_str(),
+                        profile.name,
+                        e
+                    );
+                })
+                .ok()?;
+
+            // frecency still works from the entries alone if the visits can't be read
+            let visits = reader
+                .read_visits(&sqlite.conn, previous.last_visit_time)
+                .map_err(|e| {
+                    eprintln!(
+                        "Error reading {} visits for profile '{}': {:?}",
+                        reader.browser().to_str(),
+                        profile.name,
+                        e
+                    );
+                })
+                .unwrap_or_default();
+
+            let last_visit_time = entries
+                .iter()
+                .map(|entry| entry.last_visit_time)
+                .fold(previous.last_visit_time, cmp::max);
+            let favicons = read_browser_favicons(
+                reader,
+                profile,
+                &copy_name,
+                &uncached_favicon_domains(&entries),
+            );
+            Some(ProfileSync {
+                key,
+                entries,
+                visits,
+                state: SyncState {
+                    last_visit_time,
+                    source_modified,
+                },
+                favicons,
+            })
+        })
         .collect()
 }
 
@@ -317,10 +364,9 @@ async fn cache_favicons(history: &[HistoryEntry]) {
 }
 
 async fn cache_favicon(domain: &str) {
-    match get_favicon_cache_path() {
+    match favicon_cache_file(domain) {
         None => return,
-        Some(mut path) => {
-            path.push(format!("{}.png", domain));
+        Some(path) => {
             if path.exists() {
                 return;
             }
@@ -356,14 +402,245 @@ pub enum BrowserHistoryCollationError {
 pub struct BrowserHistoryDataSource {
     name: String,
     sqlite: SQLite,
+    privacy: HistoryPrivacy,
 }
 
 impl BrowserHistoryDataSource {
+    //* Keeps the history table up to date on this connection until the app exits */
+    pub fn start_background_sync(mut self) {
+        // favicons are fetched on the async runtime, so the sync thread needs a handle to it
+        let runtime = Handle::current();
+        thread::spawn(move || {
+            let _runtime_guard = runtime.enter();
+            loop {
+                self.update_cache();
+                thread::sleep(HISTORY_SYNC_INTERVAL);
+            }
+        });
+    }
+
+    fn read_sync_states(&self) -> HashMap<String, SyncState> {
+        let query_statement = format!(
+            "SELECT profile_key, last_visit_time, source_modified FROM {}_sync_state",
+            self.name
+        );
+        self.sqlite
+            .conn
+            .prepare(&query_statement)
+            .and_then(|mut statement| {
+                statement
+                    .query_map([], |row| {
+                        Ok((
+                            row.get(0)?,
+                            SyncState {
+                                last_visit_time: row.get(1)?,
+                                source_modified: row.get(2)?,
+                            },
+                        ))
+                    })
+                    .map(|rows| rows.filter_map(Result::ok).collect())
+            })
+            .unwrap_or_else(|e| {
+                eprintln!("Error reading the history sync state: {:?}", e);
+                HashMap::new()
+            })
+    }
+
+    fn reset_sync_states(&self) {
+        if let Err(e) = self
+            .sqlite
+            .conn
+            .execute(&format!("DELETE FROM {}_sync_state", self.name), [])
+        {
+            eprintln!("Error resetting the history sync state: {:?}", e);
+        }
+    }
+
+    //* Removes every entry whose domain matches `pattern` and returns how many were removed */
+    pub fn forget_domain(&mut self, pattern: &str) -> Result<usize, DomainPatternError> {
+        let pattern = DomainPattern::parse(pattern)?;
+        if let Err(e) = self.sqlite.conn.busy_timeout(FORGET_BUSY_TIMEOUT) {
+            eprintln!("Error setting the history DB busy timeout: {:?}", e);
+        }
+        Ok(self.delete_entries_where(|url| {
+            Url::parse(url)
+                .ok()
+                .and_then(|url| url.host_str().map(str::to_lowercase))
+                .map_or(false, |host| pattern.matches(&host))
+        }))
+    }
+
+    // domain rules can't be expressed in SQL, so every url is checked here instead
+    fn delete_entries_where(&mut self, should_delete: impl Fn(&str) -> bool) -> usize {
+        let result: Result<usize, rusqlite::Error> = (|| {
+            let urls: Vec<String> = self
+                .sqlite
+                .conn
+                .prepare(&format!("SELECT url FROM {}", self.name))?
+                .query_map([], |row| row.get(0))?
+                .filter_map(Result::ok)
+                .filter(|url: &String| should_delete(url))
+                .collect();
+
+            let transaction = self.sqlite.conn.transaction()?;
+            {
+                let mut delete =
+                    transaction.prepare(&format!("DELETE FROM {} WHERE url = ?1", self.name))?;
+                let mut delete_visits = transaction
+                    .prepare(&format!("DELETE FROM {}_visits WHERE url = ?1", self.name))?;
+                for url in urls.iter() {
+                    delete.execute([url])?;
+                    delete_visits.execute([url])?;
+                }
+            }
+            transaction.commit()?;
+            Ok(urls.len())
+        })();
+
+        result.unwrap_or_else(|e| {
+            eprintln!("Error removing entries from the history DB: {:?}", e);
+            0
+        })
+    }
+
+    // applies privacy settings that changed since the entries were synced: disabled
+    // browsers, new deny rules and a shorter retention window
+    fn purge_disallowed(&mut self, home: &Path) {
+        let retention = self.retention_modifier();
+        let result: Result<(), rusqlite::Error> = (|| {
+            let transaction = self.sqlite.conn.transaction()?;
+            for browser in self.privacy.disabled_browsers() {
+                transaction.execute(
+                    &format!("DELETE FROM {} WHERE browser = ?1", self.name),
+                    [browser.to_str()],
+                )?;
+            }
+            // forget how far disabled profiles were synced so re-enabling them reads everything again
+            for reader in history_readers()
+                .iter()
+                .filter(|reader| !self.privacy.is_browser_enabled(reader.browser()))
+            {
+                for profile in reader.profiles(home) {
+                    transaction.execute(
+                        &format!(
+                            "DELETE FROM {}_sync_state WHERE profile_key = ?1",
+                            self.name
+                        ),
+                        [profile.history_path.to_string_lossy()],
+                    )?;
+                }
+            }
+            transaction.execute(
+                &format!(
+                    "DELETE FROM {} WHERE last_visit_time < strftime('%s', 'now', ?1)",
+                    self.name
+                ),
+                [retention],
+            )?;
+            transaction.execute(
+                &format!(
+                    "DELETE FROM {0}_visits WHERE url NOT IN (SELECT url FROM {0})",
+                    self.name
+                ),
+                [],
+            )?;
+            transaction.commit()
+        })();
+        if let Err(e) = result {
+            eprintln!("Error purging the history DB: {:?}", e);
+        }
+
+        let privacy = self.privacy.clone();
+        let removed = self.delete_entries_where(|url| !privacy.allows_url(url));
+        if removed > 0 {
+            println!(
+                "Removed {} history entries blocked by domain rules",
+                removed
+            );
+        }
+    }
+
+    fn read_visits(&self) -> HashMap<String, Vec<Visit>> {
+        let mut visits: HashMap<String, Vec<Visit>> = HashMap::new();
+        let result = self
+            .sqlite
+            .conn
+            .prepare(&format!(
+                "SELECT url, visit_time, kind FROM {}_visits",
+                self.name
+            ))
+            .and_then(|mut statement| {
+                let rows = statement.query_map([], |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        Visit {
+                            time: row.get(1)?,
+                            kind: VisitKind::from_i64(row.get(2)?),
+                        },
+                    ))
+                })?;
+                for (url, visit) in rows.filter_map(Result::ok) {
+                    visits.entry(url).or_default().push(visit);
+                }
+                Ok(())
+            });
+        if let Err(e) = result {
+            eprintln!("Error reading history visits: {:?}", e);
+        }
+        visits
+    }
+
+    // recency counts towards frecency, so every score is recomputed on each sync
+    // and not only the ones that were visited again
+    fn update_frecency_scores(&mut self, now: i64) {
+        let visits = self.read_visits();
+        let result: Result<(), rusqlite::Error> = (|| {
+            let scores: Vec<(String, f64)> = self
+                .sqlite
+                .conn
+                .prepare(&format!(
+                    "SELECT url, visit_count, last_visit_time FROM {}",
+                    self.name
+                ))?
+                .query_map([], |row| {
+                    let url: String = row.get(0)?;
+                    let score = calculate_frecency(
+                        row.get(1)?,
+                        row.get(2)?,
+                        visits.get(&url).map(Vec::as_slice).unwrap_or(&[]),
+                        now,
+                    );
+                    Ok((url, score))
+                })?
+                .filter_map(Result::ok)
+                .collect();
+
+            let transaction = self.sqlite.conn.transaction()?;
+            {
+                let mut update = transaction.prepare(&format!(
+                    "UPDATE {} SET frecency_score = ?2 WHERE url = ?1",
+                    self.name
+                ))?;
+                for (url, score) in scores.iter() {
+                    update.execute(params![url, score])?;
+                }
+            }
+            transaction.commit()
+        })();
+        if let Err(e) = result {
+            eprintln!("Error updating frecency scores: {:?}", e);
+        }
+    }
+
+    fn retention_modifier(&self) -> String {
+        format!("-{} days", self.privacy.retention_days)
+    }
+
     pub fn read(&self) -> Option<Vec<HistoryEntry>> {
         let query_statement = format!(
             r#"SELECT * FROM {}
-                WHERE last_visit_time >= strftime('%s', 'now', '-6 months')
-                ORDER BY frecency_score DESC
+                WHERE last_visit_time >= strftime('%s', 'now', ?1)
+                ORDER BY frecency_score DESC, last_visit_time DESC
                 LIMIT 1000"#,
             self.name
         );
@@ -378,10 +655,12 @@ impl BrowserHistoryDataSource {
             .ok()
             .and_then(|mut statement| {
                 statement
-                    .query_map([], |row| {
+                    .query_map([self.retention_modifier()], |row| {
                         Ok(HistoryEntry {
                             browser: Browser::from_string(row.get("browser")?),
+                            profile: row.get("profile")?,
                             url: row.get("url")?,
+                            original_url: row.get("original_url")?,
                             title: row.get("title")?,
                             visit_count: row.get("visit_count")?,
                             last_visit_time: row.get("last_visit_time")?,
@@ -400,7 +679,9 @@ impl DataSource<Vec<HistoryEntry>> for BrowserHistoryDataSource {
             let transaction = format!(
                 "CREATE TABLE IF NOT EXISTS {} (
                   browser TEXT NOT NULL,
+                  profile TEXT NOT NULL DEFAULT '',
                   url TEXT PRIMARY KEY NOT NULL UNIQUE,
+                  original_url TEXT NOT NULL,
                   title TEXT NOT NULL,
                   visit_count INTEGER NOT NULL,
                   last_visit_time INTEGER NOT NULL,
@@ -414,9 +695,65 @@ impl DataSource<Vec<HistoryEntry>> for BrowserHistoryDataSource {
                     name, e
                 )
             };
+            let sync_state_table = format!(
+                "CREATE TABLE IF NOT EXISTS {}_sync_state (
+                  profile_key TEXT PRIMARY KEY NOT NULL,
+                  last_visit_time INTEGER NOT NULL,
+                  source_modified INTEGER NOT NULL
+                )",
+                name
+            );
+            if let Err(e) = sqlite.conn.execute(&sync_state_table, []) {
+                eprintln!("failed to create the table '{}_sync_state'\n{:?}", name, e)
+            };
+            // the most recent visits of every url, used to score its frecency
+            let visits_table = format!(
+                "CREATE TABLE IF NOT EXISTS {0}_visits (
+                  url TEXT NOT NULL,
+                  visit_time INTEGER NOT NULL,
+                  kind INTEGER NOT NULL,
+                  UNIQUE (url, visit_time, kind)
+                );
+                CREATE INDEX IF NOT EXISTS {0}_visits_url ON {0}_visits (url)",
+                name
+            );
+            if let Err(e) = sqlite.conn.execute_batch(&visits_table) {
+                eprintln!("failed to create the table '{}_visits'\n{:?}", name, e)
+            };
+            // databases created before profiles were tracked are missing the column
+            sqlite
+                .conn
+                .execute(
+                    &format!(
+                        "ALTER TABLE {} ADD COLUMN profile TEXT NOT NULL DEFAULT ''",
+                        name
+                    ),
+                    [],
+                )
+                .ok();
+            // older databases keyed entries on urls without their query and fragment,
+            // which merged distinct pages, so they are synced again from scratch
+            let added_original_url = sqlite.conn.execute(
+                &format!(
+                    "ALTER TABLE {} ADD COLUMN original_url TEXT NOT NULL DEFAULT ''",
+                    name
+                ),
+                [],
+            );
+            if added_original_url.is_ok() {
+                sqlite
+                    .conn
+                    .execute_batch(&format!(
+                        "DELETE FROM {0}; DELETE FROM {0}_visits; DELETE FROM {0}_sync_state;",
+                        name
+                    ))
+                    .map_err(|e| eprintln!("Error resetting the history DB: {:?}", e))
+                    .ok();
+            }
             Self {
                 sqlite,
                 name: name.to_string(),
+                privacy: HistoryPrivacy::current(),
             }
         } else {
             panic!("Error initializing the BrowserHistoryDataSource")
@@ -424,135 +761,153 @@ impl DataSource<Vec<HistoryEntry>> for BrowserHistoryDataSource {
     }
 
     fn update_cache(&mut self) {
-        let schemas: Vec<HistorySchema> = get_history_schemas();
-
-        schemas.iter().for_each(|schema| {
-            if schema.path.contains("*") {
-                for (i, entry) in glob(&schema.path)
-                    .expect("Failed to read glob pattern")
-                    .enumerate()
-                {
-                    match entry {
-                        Ok(path) => {
-                            copy_browser_sqlite_to_tmpdir(
-                                path.to_str().unwrap(),
-                                &format!("{}-{}", &schema.browser.to_str(), i + 1),
-                            );
-                        }
-                        Err(e) => println!("{:?}", e),
-                    }
-                }
-            } else {
-                copy_browser_sqlite_to_tmpdir(&schema.path, schema.browser.to_str());
+        let home = match dirs::home_dir() {
+            Some(home) => home,
+            None => {
+                eprintln!("Unable to locate the home directory, skipping browser history");
+                return;
             }
-        });
+        };
+        let start = Instant::now();
+        // a config that can't be read right now keeps the previous privacy settings
+        if let Some(config) = AppConfig::read() {
+            let privacy = HistoryPrivacy::from_config(&config.browser_history);
+            // what a looser rule now lets through was skipped by the earlier syncs, so
+            // every profile is read again from the start
+            if !privacy.filters_like(&self.privacy) {
+                self.reset_sync_states();
+            }
+            self.privacy = privacy;
+        }
+        self.purge_disallowed(&home);
+        let sync_states = self.read_sync_states();
 
-        let schema_with_paths: Vec<(HistorySchema, Vec<String>)> = schemas
-            .iter()
-            .map(|schema| {
-                (
-                    schema.to_owned(),
-                    get_copied_sqlite_paths_for_history_schema(schema),
-                )
-            })
+        let profile_syncs: Vec<ProfileSync> = history_readers()
+            .par_iter()
+            .filter(|reader| self.privacy.is_browser_enabled(reader.browser()))
+            .flat_map(|reader| read_browser_history(reader.as_ref(), &home, &sync_states))
             .collect();
 
-        let entries: Vec<HistoryEntry> = schema_with_paths
-            .par_iter()
-            .flat_map(|(schema, paths)| {
-                paths.par_iter().filter_map(|path| {
-                    SQLite::from_path(path, true)
-                        .map_err(|e| {
-                            eprintln!("Error connecting to db: {:?}", e);
-                        })
-                        .ok()
-                        .and_then(|sqlite| {
-                            sqlite.conn.prepare(schema.query.as_str()).ok().and_then(
-                                |mut statement| {
-                                    statement
-                                        .query_map([], |row| {
-                                            let mut entry = HistoryEntry {
-                                                browser: schema.browser,
-                                                url: row.get_unwrap(1),
-                                                title: row.get_unwrap(2),
-                                                visit_count: row.get_unwrap(3),
-                                                last_visit_time: row.get_unwrap(4),
-                                                frecency_score: 0.0,
-                                            };
-                                            entry.frecency_score = calculate_frecency(&entry);
-
-                                            if let Ok(mut url) = Url::parse(entry.url.as_str()) {
-                                                url.set_query(None);
-                                                url.set_fragment(None);
-                                                entry.url = url
-                                                    .as_str()
-                                                    .trim_end_matches(&['/', '#', '?', '&'])
-                                                    .to_string()
-                                            }
-                                            Ok(entry)
-                                        })
-                                        .ok()
-                                        .map(|rows| {
-                                            rows.filter_map(Result::ok)
-                                                .filter(|row| {
-                                                    !row.url.contains("localhost")
-                                                        && !row.url.contains("127.0.0.1")
-                                                        && !row.url.contains("0.0.0.0")
-                                                        && !row.url.contains("file://")
-                                                        && row.frecency_score > 0.0
-                                                })
-                                                .collect::<Vec<HistoryEntry>>()
-                                        })
-                                },
-                            )
-                        })
+        let entries: Vec<HistoryEntry> = merge_duplicate_entries(
+            profile_syncs
+                .iter()
+                .flat_map(|profile_sync| profile_sync.entries.iter().cloned())
+                .map(|mut entry| {
+                    entry.url = normalize_url(&entry.original_url);
+                    entry
                 })
-            })
-            .flatten()
-            .collect::<Vec<_>>();
+                .filter(|row| self.privacy.allows_url(&row.url))
+                .collect(),
+        );
+        let visits: Vec<(String, Visit)> = profile_syncs
+            .iter()
+            .flat_map(|profile_sync| profile_sync.visits.iter())
+            .map(|(url, visit)| (normalize_url(url), *visit))
+            .filter(|(url, _)| self.privacy.allows_url(url))
+            .collect();
 
-        let cacheable = entries.clone();
-        spawn(async move {
-            cache_favicons(&cacheable).await;
-        });
+        if profile_syncs.is_empty() {
+            self.update_frecency_scores(Local::now().timestamp());
+            return;
+        }
 
-        if let Ok(transaction) = self.sqlite.conn.transaction() {
-            let result: Result<(), rusqlite::Error> = (|| {
-                match transaction.prepare(
-                    &format!("INSERT OR IGNORE INTO {} (browser, url, title, visit_count, last_visit_time, frecency_score) VALUES (?1, ?2, ?3, ?4, ?5, ?6)", self.name),
-                ) {
-                  Ok(mut statement) => {
-                    for entry in entries {
-                        statement.execute(params![
-                            entry.browser.to_str(),
-                            entry.url,
-                            entry.title,
-                            entry.visit_count,
-                            entry.last_visit_time,
-                            entry.frecency_score
-                        ])?;
-                    }
-                  },
-                  Err(e) => eprintln!("Error writing history entry: {:?}", e)
-                };
+        // only domains that made it past the privacy rules get their icon cached
+        let allowed_domains: HashSet<String> = entries
+            .iter()
+            .filter_map(|entry| Url::parse(&entry.url).ok())
+            .filter_map(|url| url.domain().map(str::to_string))
+            .collect();
+        let favicons: HashMap<String, Vec<u8>> = profile_syncs
+            .iter()
+            .flat_map(|profile_sync| profile_sync.favicons.iter())
+            .filter(|(domain, _)| allowed_domains.contains(*domain))
+            .map(|(domain, image)| (domain.clone(), image.clone()))
+            .collect();
+        write_favicons(&favicons);
 
-                Ok(())
-            })();
+        if self.privacy.fetch_missing_favicons {
+            let cacheable = entries.clone();
+            spawn(async move {
+                cache_favicons(&cacheable).await;
+            });
+        }
 
-            match result {
-                Ok(_) => {
-                    if let Err(e) = transaction.commit() {
-                        eprintln!("Error committing browser history to collated DB: {:?}", e);
-                    }
+        let result: Result<(), rusqlite::Error> = (|| {
+            let transaction = self.sqlite.conn.transaction()?;
+            {
+                // the same rules as merge_duplicate_entries: browsers only ever increase a
+                // url's visit count, so the highest count seen is kept, and the rest comes
+                // from the most recent visit
+                let mut upsert = transaction.prepare(&format!(
+                    "INSERT INTO {} (browser, profile, url, original_url, title, visit_count, last_visit_time, frecency_score)
+                    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+                    ON CONFLICT(url) DO UPDATE SET
+                        browser = CASE WHEN excluded.last_visit_time >= last_visit_time THEN excluded.browser ELSE browser END,
+                        profile = CASE WHEN excluded.last_visit_time >= last_visit_time THEN excluded.profile ELSE profile END,
+                        original_url = CASE WHEN excluded.last_visit_time >= last_visit_time THEN excluded.original_url ELSE original_url END,
+                        title = CASE WHEN excluded.title != '' AND (excluded.last_visit_time >= last_visit_time OR title = '') THEN excluded.title ELSE title END,
+                        visit_count = MAX(visit_count, excluded.visit_count),
+                        last_visit_time = MAX(last_visit_time, excluded.last_visit_time)",
+                    self.name
+                ))?;
+                for entry in entries.iter() {
+                    upsert.execute(params![
+                        entry.browser.to_str(),
+                        entry.profile,
+                        entry.url,
+                        entry.original_url,
+                        entry.title,
+                        entry.visit_count,
+                        entry.last_visit_time,
+                        entry.frecency_score
+                    ])?;
                 }
-                Err(e) => {
-                    eprintln!("Error inserting browser history into collated DB: {:?}", e);
-                    // Optionally, you might want to roll back the transaction here
-                    // if let Err(rollback_err) = transaction.rollback() {
-                    //     eprintln!("Error rolling back transaction: {:?}", rollback_err);
-                    // }
+
+                let mut insert_visit = transaction.prepare(&format!(
+                    "INSERT OR IGNORE INTO {}_visits (url, visit_time, kind) VALUES (?1, ?2, ?3)",
+                    self.name
+                ))?;
+                for (url, visit) in visits.iter() {
+                    insert_visit.execute(params![url, visit.time, visit.kind.to_i64()])?;
+                }
+
+                let mut save_state = transaction.prepare(&format!(
+                    "INSERT OR REPLACE INTO {}_sync_state (profile_key, last_visit_time, source_modified) VALUES (?1, ?2, ?3)",
+                    self.name
+                ))?;
+                for profile_sync in profile_syncs.iter() {
+                    save_state.execute(params![
+                        profile_sync.key,
+                        profile_sync.state.last_visit_time,
+                        profile_sync.state.source_modified
+                    ])?;
                 }
             }
+            // only the most recent visits are sampled, older ones are dead weight
+            transaction.execute(
+                &format!(
+                    "DELETE FROM {0}_visits WHERE rowid IN (
+                        SELECT rowid FROM (
+                            SELECT rowid, ROW_NUMBER() OVER (PARTITION BY url ORDER BY visit_time DESC) AS recency
+                            FROM {0}_visits
+                        ) WHERE recency > ?1
+                    )",
+                    self.name
+                ),
+                [MAX_SAMPLED_VISITS as i64],
+            )?;
+            transaction.commit()
+        })();
+        self.update_frecency_scores(Local::now().timestamp());
+
+        match result {
+            Ok(_) => println!(
+                "Synced {} browser history entries from {} profiles in {}ms",
+                entries.len(),
+                profile_syncs.len(),
+                start.elapsed().as_millis()
+            ),
+            Err(e) => eprintln!("Error syncing browser history into collated DB: {:?}", e),
         }
     }
 
@@ -563,7 +918,7 @@ impl DataSource<Vec<HistoryEntry>> for BrowserHistoryDataSource {
                 let start = Instant::now();
 
                 let matcher = SkimMatcherV2::default().ignore_case();
-                let mut scored_entries: Vec<(i64, HistoryEntry)> = entries
+                let mut scored_entries: Vec<(f64, HistoryEntry)> = entries
                     .par_iter()
                     .filter_map(|entry| {
                         let url = entry.url.clone();
@@ -581,7 +936,7 @@ impl DataSource<Vec<HistoryEntry>> for BrowserHistoryDataSource {
 
                         score = cmp::max(score, title_score);
                         if score > 0 {
-                            Some((score, entry.to_owned()))
+                            Some((blended_score(score, entry.frecency_score), entry.to_owned()))
                         } else {
                             None
                         }
@@ -592,7 +947,7 @@ impl DataSource<Vec<HistoryEntry>> for BrowserHistoryDataSource {
                     start.elapsed().as_millis()
                 );
 
-                scored_entries.sort_by(|a, b| b.0.cmp(&a.0));
+                scored_entries.sort_by(|a, b| b.0.total_cmp(&a.0));
                 Some(
                     scored_entries
                         .iter()
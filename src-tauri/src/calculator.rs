@@ -0,0 +1,418 @@
+use std::error::Error;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use fend_core::{Context, ExchangeRateFn, FendResult, Interrupt, SpanKind};
+use swordfish_types::{Query, QueryFilter, QueryMode, ResultDetails, ResultItem, ResultType};
+use v_htmlescape::escape;
+
+use crate::calculator_history::{Calculation, CalculatorHistory};
+use crate::exchange_rates::{ExchangeRateSource, ExchangeRates};
+use crate::result_providers::{ProviderResult, ResultProvider};
+
+// something like 10^10^10 would otherwise keep a thread busy long after the
+// result is dropped
+const EVALUATION_TIMEOUT: Duration = Duration::from_millis(500);
+const MAX_HISTORY_RESULTS: usize = 100;
+// a result shown this long in the calculator mode has been read, and isn't just a
+// prefix of the expression being typed
+const SETTLE_DELAY: Duration = Duration::from_millis(1500);
+const SETTLE_CHECK_INTERVAL: Duration = Duration::from_millis(250);
+
+struct Deadline(Instant);
+
+impl Interrupt for Deadline {
+    fn should_interrupt(&self) -> bool {
+        Instant::now() > self.0
+    }
+}
+
+// the rates at the time of one evaluation, `used` tells whether it converted currencies
+struct ExchangeRateLookup {
+    rates: Arc<ExchangeRates>,
+    used: Arc<AtomicBool>,
+}
+
+impl ExchangeRateFn for ExchangeRateLookup {
+    fn relative_to_base_currency(
+        &self,
+        currency: &str,
+    ) -> Result<f64, Box<dyn Error + Send + Sync + 'static>> {
+        self.used.store(true, Ordering::Relaxed);
+        self.rates
+            .per_us_dollar(currency)
+            .ok_or_else(|| format!("there is no exchange rate for {}", currency).into())
+    }
+}
+
+#[derive(Default)]
+struct Settling {
+    /* the expression the calculator mode shows a result for, and since when */
+    pending: Option<(String, Instant)>,
+    /* recorded because it settled, picking it afterwards doesn't record it twice */
+    settled: Option<String>,
+}
+
+//* The calculator's session, shared by the providers answering queries and the command recording picked results */
+#[derive(Clone)]
+pub struct Calculator {
+    context: Arc<Mutex<Context>>,
+    exchange_rates: ExchangeRateSource,
+    history: Arc<Mutex<CalculatorHistory>>,
+    settling: Arc<Mutex<Settling>>,
+}
+
+impl Calculator {
+    pub fn new(exchange_rates: ExchangeRateSource) -> Self {
+        Self {
+            context: Arc::new(Mutex::new(Context::new())),
+            exchange_rates,
+            history: Arc::new(Mutex::new(CalculatorHistory::new("calculator_history"))),
+            settling: Arc::new(Mutex::new(Settling::default())),
+        }
+    }
+
+    // Along with the result come the rates it was converted with, if any
+    fn evaluate(
+        &self,
+        input: &str,
+        context: &mut Context,
+    ) -> Option<(FendResult, Option<Arc<ExchangeRates>>)> {
+        let rates = self.exchange_rates.current();
+        let used = Arc::new(AtomicBool::new(false));
+        if let Some(rates) = rates.clone() {
+            context.set_exchange_rate_handler_v1(ExchangeRateLookup {
+                rates,
+                used: Arc::clone(&used),
+            });
+        }
+        let deadline = Deadline(Instant::now() + EVALUATION_TIMEOUT);
+        let result = fend_core::evaluate_with_interrupt(input, context, &deadline).ok()?;
+        Some((result, rates.filter(|_| used.load(Ordering::Relaxed))))
+    }
+
+    // Queries are sent on every keystroke, so they're evaluated against a copy of the
+    // session context that's thrown away afterwards. Otherwise typing "ans * 2" would
+    // double `ans` on the way. Assignments are the exception, "x = 5" defines x for the
+    // queries after it whether or not it's picked.
+    fn preview(&self, input: &str) -> Option<(FendResult, Option<Arc<ExchangeRates>>)> {
+        let mut context = self.context.lock().ok()?;
+        if is_assignment(input) {
+            return self.evaluate(input, &mut context);
+        }
+        let mut context = context.clone();
+        self.evaluate(input, &mut context)
+    }
+
+    //* Records the result the calculator mode shows once it stops changing, see `record` */
+    pub fn start_settling(&self) {
+        let calculator = self.clone();
+        thread::spawn(move || loop {
+            thread::sleep(SETTLE_CHECK_INTERVAL);
+            calculator.settle();
+        });
+    }
+
+    // every query replaces the result that's about to settle, None when it has none
+    fn show(&self, expression: Option<&str>) {
+        if let Ok(mut settling) = self.settling.lock() {
+            settling.pending =
+                expression.map(|expression| (expression.to_string(), Instant::now()));
+            settling.settled = None;
+        }
+    }
+
+    fn settle(&self) {
+        let expression = match self.settling.lock() {
+            Ok(mut settling) => match &settling.pending {
+                Some((_, shown)) if shown.elapsed() >= SETTLE_DELAY => {
+                    settling.pending.take().map(|(expression, _)| expression)
+                }
+                _ => None,
+            },
+            Err(_) => None,
+        };
+        let expression = match expression {
+            Some(expression) => expression,
+            None => return,
+        };
+        match self.evaluate_and_record(&expression) {
+            Ok(()) => {
+                if let Ok(mut settling) = self.settling.lock() {
+                    // unless another query came in while it was recorded
+                    if settling.pending.is_none() {
+                        settling.settled = Some(expression);
+                    }
+                }
+            }
+            Err(e) => eprintln!("Error recording the calculation '{}': {}", expression, e),
+        }
+    }
+
+    //* Evaluates a calculation in the session context, so the variables it defines and its result as `ans` can be used by later queries, and adds it to the history. That happens when a result is picked, or when the calculator mode has shown it for a moment. The results shown while typing would otherwise fill the history with every prefix of an expression. */
+    pub fn record(&self, expression: &str) -> Result<(), String> {
+        let expression = expression.trim();
+        if let Ok(mut settling) = self.settling.lock() {
+            settling.pending = None;
+            if settling.settled.take().as_deref() == Some(expression) {
+                return Ok(());
+            }
+        }
+        self.evaluate_and_record(expression)
+    }
+
+    fn evaluate_and_record(&self, expression: &str) -> Result<(), String> {
+        let calculation = {
+            let mut context = self.context.lock().map_err(|e| e.to_string())?;
+            // evaluated once, so `ans` is what's recorded even for something random like
+            // "roll d20". Expressions that can't be wrapped, e.g. assignments, are
+            // evaluated on their own and leave `ans` at the previous result.
+            let (fend_result, _) = self
+                .evaluate(&format!("ans = ({})", expression), &mut context)
+                .or_else(|| self.evaluate(expression, &mut context))
+                .ok_or_else(|| format!("'{}' can't be calculated", expression))?;
+            Calculation::new(
+                expression,
+                fend_result.get_main_result(),
+                &format_calculator_result(&fend_result),
+            )
+        };
+        self.history
+            .lock()
+            .map_err(|e| e.to_string())?
+            .record(&calculation)
+            .map_err(|e| e.to_string())
+    }
+}
+
+// "x = 5" or "x := 5", but not a comparison like "x == 5"
+fn is_assignment(input: &str) -> bool {
+    let name_end = input
+        .find(|c: char| !c.is_alphanumeric() && c != '_')
+        .unwrap_or(input.len());
+    let name = &input[..name_end];
+    let rest = input[name_end..].trim_start();
+    !name.is_empty()
+        && !name.starts_with(|c: char| c.is_ascii_digit())
+        && (rest.starts_with(":=") || (rest.starts_with('=') && !rest.starts_with("==")))
+}
+
+// outside of the calculator mode, words and unit names like "m" or "pi" would evaluate
+// too, so only an operator, a conversion or a function call makes a calculation
+fn looks_like_calculation(input: &str) -> bool {
+    let has_operator = input.contains(|c: char| "+-*/^%=!×÷".contains(c));
+    let has_conversion = input
+        .split_whitespace()
+        .skip(1)
+        .any(|word| ["to", "in", "as"].contains(&word.to_lowercase().as_str()));
+    let has_function_call = input.match_indices('(').any(|(index, _)| {
+        input[..index]
+            .chars()
+            .next_back()
+            .map_or(false, |c| c.is_alphabetic())
+    });
+    has_operator || has_conversion || has_function_call
+}
+
+// a result and the same calculation in the history are only listed once
+fn calculation_key(expression: &str) -> String {
+    format!("calculation:{}", expression.trim())
+}
+
+//* Evaluates queries with fend */
+pub struct CalculatorProvider {
+    calculator: Calculator,
+}
+
+impl CalculatorProvider {
+    pub fn new(calculator: Calculator) -> Self {
+        Self { calculator }
+    }
+
+    fn calculate(&self, query: &Query, filters: &[QueryFilter]) -> Vec<ProviderResult> {
+        let input = query.search_string.trim();
+        if input.is_empty()
+            || !filters.is_empty()
+            || (query.mode != QueryMode::Calculator && !looks_like_calculation(input))
+        {
+            return vec![];
+        }
+        let (fend_result, exchange_rates) = match self.calculator.preview(input) {
+            Some(evaluated) => evaluated,
+            None => return vec![],
+        };
+        let main_result = fend_result.get_main_result();
+        // outside of the calculator mode, a number on its own isn't worth a result
+        if main_result.is_empty() || (query.mode != QueryMode::Calculator && main_result == input) {
+            return vec![];
+        }
+
+        let formatted = format_calculator_result(&fend_result);
+        let subheading = match exchange_rates {
+            Some(rates) => format!(
+                "{}<span class=\"calculator-rates\">Exchange rates of {}</span>",
+                formatted,
+                escape(&rates.date)
+            ),
+            None => formatted.clone(),
+        };
+        let item = ResultItem {
+            heading: main_result.to_string(),
+            subheading,
+            value: main_result.to_string(),
+            icon_path: None,
+            r#type: ResultType::Calculator,
+            details: Some(ResultDetails::Calculator {
+                parsed_content: formatted,
+                expression: input.to_string(),
+            }),
+        };
+        vec![ProviderResult {
+            key: calculation_key(input),
+            ..ProviderResult::ranked(item, 0)
+        }]
+    }
+}
+
+impl ResultProvider for CalculatorProvider {
+    fn id(&self) -> &str {
+        "calculator"
+    }
+
+    fn section(&self) -> &str {
+        "Calculator"
+    }
+
+    // chat has no result list to show it in
+    fn modes(&self) -> &[QueryMode] {
+        &[
+            QueryMode::Calculator,
+            QueryMode::Search,
+            QueryMode::BrowserHistory,
+            QueryMode::Scripts,
+        ]
+    }
+
+    fn query(&self, query: &Query, filters: &[QueryFilter]) -> Vec<ProviderResult> {
+        let results = self.calculate(query, filters);
+        let shown = query.mode == QueryMode::Calculator && !results.is_empty();
+        self.calculator
+            .show(Some(query.search_string.trim()).filter(|_| shown));
+        results
+    }
+}
+
+//* Earlier calculations, all of them when the calculator is opened without an expression */
+pub struct CalculatorHistoryProvider {
+    calculator: Calculator,
+}
+
+impl CalculatorHistoryProvider {
+    pub fn new(calculator: Calculator) -> Self {
+        Self { calculator }
+    }
+}
+
+impl ResultProvider for CalculatorHistoryProvider {
+    fn id(&self) -> &str {
+        "calculator_history"
+    }
+
+    fn section(&self) -> &str {
+        "Earlier calculations"
+    }
+
+    fn modes(&self) -> &[QueryMode] {
+        &[QueryMode::Calculator]
+    }
+
+    // the limit is applied by the registry, this only keeps the query small
+    fn query(&self, query: &Query, _filters: &[QueryFilter]) -> Vec<ProviderResult> {
+        let calculations = match self.calculator.history.lock() {
+            Ok(history) => history.search(&query.search_string, MAX_HISTORY_RESULTS),
+            Err(_) => return vec![],
+        };
+        match calculations {
+            Ok(calculations) => calculations
+                .iter()
+                .enumerate()
+                .map(|(rank, calculation)| ProviderResult {
+                    key: calculation_key(&calculation.expression),
+                    ..ProviderResult::ranked(calculation_result(calculation), rank)
+                })
+                .collect(),
+            Err(e) => {
+                eprintln!("Error searching the calculator history: {:?}", e);
+                vec![]
+            }
+        }
+    }
+}
+
+fn calculation_result(calculation: &Calculation) -> ResultItem {
+    ResultItem {
+        heading: calculation.result.clone(),
+        subheading: format!(
+            "<span class=\"calculator-expression\">{}</span>",
+            escape(&calculation.expression)
+        ),
+        value: calculation.result.clone(),
+        icon_path: None,
+        r#type: ResultType::Calculator,
+        details: Some(ResultDetails::Calculator {
+            parsed_content: calculation.formatted.clone(),
+            expression: calculation.expression.clone(),
+        }),
+    }
+}
+
+fn format_calculator_result(fend_result: &FendResult) -> String {
+    let calculator_content = fend_result
+        .get_main_result_spans()
+        .filter(|span| !span.string().is_empty())
+        .map(|span| {
+            let class = match span.kind() {
+                SpanKind::Boolean => "calculator-boolean",
+                SpanKind::Number => "calculator-number",
+                SpanKind::BuiltInFunction => "calculator-builtin-fn",
+                SpanKind::Keyword => "calculator-keyword",
+                SpanKind::String => "calculator-string",
+                SpanKind::Date => "calculator-date",
+                SpanKind::Whitespace => "calculator-whitespace",
+                SpanKind::Ident => "calculator-ident",
+                SpanKind::Other => "calculator-other",
+                _ => "calculator-unknown",
+            };
+            format!("<span class=\"{}\">{}</span>", class, escape(span.string()))
+        })
+        .collect::<Vec<_>>()
+        .join("");
+    format!("<span class=\"calculator\">{}</span>", calculator_content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recognizes_assignments() {
+        for input in ["x = 5", "x:=5", "my_var=3"] {
+            assert!(is_assignment(input), "{}", input);
+        }
+        for input in ["x == 5", "5 = 5", "x", "= 5"] {
+            assert!(!is_assignment(input), "{}", input);
+        }
+    }
+
+    #[test]
+    fn only_operators_conversions_and_calls_are_calculations() {
+        for input in ["2+2", "x = 5", "10 m to ft", "5 in cm", "sqrt(2)", "5!"] {
+            assert!(looks_like_calculation(input), "{}", input);
+        }
+        for input in ["pi", "m", "kg", "day", "hour", "firefox", "in", "(notes)"] {
+            assert!(!looks_like_calculation(input), "{}", input);
+        }
+    }
+}
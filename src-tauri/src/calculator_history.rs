@@ -0,0 +1,97 @@
+use crate::sqlite::SQLite;
+use rusqlite::params;
+use std::time::SystemTime;
+use swordfish_types::unix_timestamp;
+
+//* A calculation whose result was picked, `formatted` is the result as HTML spans */
+#[derive(Debug, Clone, PartialEq)]
+pub struct Calculation {
+    pub expression: String,
+    pub result: String,
+    pub formatted: String,
+    /* unix timestamp in seconds */
+    pub evaluated_at: i64,
+}
+
+impl Calculation {
+    pub fn new(expression: &str, result: &str, formatted: &str) -> Self {
+        Self {
+            expression: expression.to_string(),
+            result: result.to_string(),
+            formatted: formatted.to_string(),
+            evaluated_at: unix_timestamp(SystemTime::now()),
+        }
+    }
+}
+
+pub struct CalculatorHistory {
+    name: String,
+    sqlite: SQLite,
+}
+
+impl CalculatorHistory {
+    pub fn new(name: &str) -> Self {
+        if let Ok(sqlite) = SQLite::new(name, false) {
+            let transaction = format!(
+                "CREATE TABLE IF NOT EXISTS {} (
+                  expression TEXT NOT NULL UNIQUE,
+                  result TEXT NOT NULL,
+                  formatted TEXT NOT NULL,
+                  evaluated_at INTEGER NOT NULL
+                )",
+                name
+            );
+            if let Err(e) = sqlite.conn.execute(&transaction, []) {
+                eprintln!(
+                    "failed to create the table '{}', maybe it already exists?\n{:?}",
+                    name, e
+                )
+            };
+            Self {
+                sqlite,
+                name: name.to_string(),
+            }
+        } else {
+            panic!("Error initializing the CalculatorHistory")
+        }
+    }
+
+    //* An expression that was calculated before moves to the top with its new result */
+    pub fn record(&self, calculation: &Calculation) -> Result<(), rusqlite::Error> {
+        self.sqlite.conn.execute(
+            &format!(
+                "INSERT OR REPLACE INTO {} (expression, result, formatted, evaluated_at) VALUES (?1, ?2, ?3, ?4)",
+                self.name
+            ),
+            params![
+                calculation.expression,
+                calculation.result,
+                calculation.formatted,
+                calculation.evaluated_at
+            ],
+        )?;
+        Ok(())
+    }
+
+    //* The latest calculations whose expression or result contains `term`, all of them when it's empty */
+    pub fn search(&self, term: &str, limit: usize) -> Result<Vec<Calculation>, rusqlite::Error> {
+        let mut statement = self.sqlite.conn.prepare(&format!(
+            "SELECT expression, result, formatted, evaluated_at FROM {}
+             WHERE instr(lower(expression), lower(?1)) > 0 OR instr(lower(result), lower(?1)) > 0
+             ORDER BY evaluated_at DESC, rowid DESC
+             LIMIT ?2",
+            self.name
+        ))?;
+        let calculations = statement
+            .query_map(params![term.trim(), limit as i64], |row| {
+                Ok(Calculation {
+                    expression: row.get(0)?,
+                    result: row.get(1)?,
+                    formatted: row.get(2)?,
+                    evaluated_at: row.get(3)?,
+                })
+            })?
+            .collect();
+        calculations
+    }
+}
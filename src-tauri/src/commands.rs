@@ -0,0 +1,163 @@
+use swordfish_types::DataSource;
+use tauri::{AppHandle, Manager};
+
+use crate::app_state::AppState;
+use crate::browser_data_source::BrowserHistoryDataSource;
+use crate::calculator::Calculator;
+use crate::history_privacy::DomainPattern;
+use crate::open_tabs_data_source::OpenTabsDataSource;
+use crate::plugin_host::PluginHost;
+use crate::settings::{AppConfig, ConfigError, SettingsState};
+use crate::shortcuts::{register_shortcuts, replace_shortcuts, suspend_launch_shortcut};
+
+//* The config in use, along with anything that was wrong with config.json when it was loaded */
+#[tauri::command]
+pub fn get_settings(app: AppHandle) -> Result<SettingsState, String> {
+    let state = app.state::<AppState>();
+    let config = state.config.lock().map_err(|e| e.to_string())?.clone();
+    let errors = state
+        .config_errors
+        .lock()
+        .map_err(|e| e.to_string())?
+        .clone();
+    Ok(SettingsState { config, errors })
+}
+
+//* Validates and saves the settings, nothing is written when any setting is invalid */
+#[tauri::command]
+pub fn save_settings(app: AppHandle, config: AppConfig) -> Result<SettingsState, Vec<ConfigError>> {
+    let errors = config.validate();
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    let state = app.state::<AppState>();
+    let config = config.write();
+    if let Ok(mut current) = state.config.lock() {
+        *current = config.clone();
+    }
+    if let Ok(mut config_errors) = state.config_errors.lock() {
+        config_errors.clear();
+    }
+    Ok(SettingsState {
+        config,
+        errors: vec![],
+    })
+}
+
+//* Lets the settings window see the launch shortcut being pressed while it records a new one */
+#[tauri::command]
+pub fn start_shortcut_recording(app: AppHandle) {
+    suspend_launch_shortcut(&app);
+}
+
+#[tauri::command]
+pub fn stop_shortcut_recording(app: AppHandle) -> Result<(), Vec<ConfigError>> {
+    let errors = register_shortcuts(&app);
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+//* Registers a new launch shortcut and saves it, the current one stays when the new one can't be used */
+#[tauri::command]
+pub fn set_launch_shortcut(app: AppHandle, shortcut: String) -> Result<SettingsState, ConfigError> {
+    let state = app.state::<AppState>();
+    let previous = state
+        .config
+        .lock()
+        .map_err(|e| ConfigError::new("launch_shortcut", e.to_string()))?
+        .clone();
+    let mut config = previous.clone();
+    config.launch_shortcut = shortcut.trim().to_string();
+    // only problems the new shortcut causes, e.g. clashing with a keybinding
+    let existing_errors = previous.validate();
+    if let Some(error) = config
+        .validate()
+        .into_iter()
+        .find(|error| !existing_errors.contains(error))
+    {
+        return Err(error);
+    }
+    if let Some(error) = replace_shortcuts(&app, &previous, &config)
+        .into_iter()
+        .find(|error| error.field == "launch_shortcut")
+    {
+        return Err(error);
+    }
+
+    let config = config.write();
+    if let Ok(mut current) = state.config.lock() {
+        *current = config.clone();
+    }
+    let errors = state
+        .config_errors
+        .lock()
+        .map(|mut errors| {
+            errors.retain(|error| error.field != "launch_shortcut");
+            errors.clone()
+        })
+        .unwrap_or_default();
+    Ok(SettingsState { config, errors })
+}
+
+//* Deletes a domain from the browser history index and adds it to the deny list so it stays forgotten */
+#[tauri::command]
+pub async fn forget_history_domain(app: AppHandle, domain: String) -> Result<usize, String> {
+    DomainPattern::parse(&domain).map_err(|e| e.to_string())?;
+
+    {
+        let state = app.state::<AppState>();
+        let mut config = state.config.lock().map_err(|e| e.to_string())?;
+        if !config.browser_history.denied_domains.contains(&domain) {
+            config.browser_history.denied_domains.push(domain.clone());
+            *config = config.write();
+        }
+    }
+
+    // the delete can wait on a running sync, so it's neither done holding the config
+    // nor on the main thread
+    tauri::async_runtime::spawn_blocking(move || {
+        let removed = BrowserHistoryDataSource::new("history")
+            .forget_domain(&domain)
+            .map_err(|e| e.to_string())?;
+        println!("Forgot {} history entries matching '{}'", removed, domain);
+        Ok(removed)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+//* Asks the plugin a result came from to run its action, without blocking the main thread while it does */
+#[tauri::command]
+pub async fn execute_plugin_action(
+    app: AppHandle,
+    plugin: String,
+    value: String,
+) -> Result<(), String> {
+    let plugins = app.state::<PluginHost>().inner().clone();
+    tauri::async_runtime::spawn_blocking(move || plugins.execute_action(&plugin, &value))
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| e.to_string())
+}
+
+//* Keeps a picked calculator result as `ans` and in the history, the expression is evaluated again off the main thread */
+#[tauri::command]
+pub async fn record_calculation(app: AppHandle, expression: String) -> Result<(), String> {
+    let calculator = app.state::<Calculator>().inner().clone();
+    tauri::async_runtime::spawn_blocking(move || calculator.record(&expression))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+//* Switches to the tab already showing `url`, resolves to false when the frontend should open it instead */
+#[tauri::command]
+pub async fn activate_open_tab(app: AppHandle, url: String) -> Result<bool, String> {
+    let open_tabs = app.state::<OpenTabsDataSource>().inner().clone();
+    tauri::async_runtime::spawn_blocking(move || open_tabs.activate(&url))
+        .await
+        .map_err(|e| e.to_string())
+}
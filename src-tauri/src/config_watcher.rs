@@ -0,0 +1,135 @@
+use std::{
+    fs,
+    path::Path,
+    thread,
+    time::{Duration, SystemTime},
+};
+
+use serde_variant::to_variant_name;
+use swordfish_types::{DataSource, SFEvent};
+use swordfish_utilities::config_filepath;
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::app_state::AppState;
+use crate::file_data_source::FileDataSource;
+use crate::settings::{AppConfig, ConfigError, SearchDirectory, SettingsState};
+use crate::shortcuts::replace_shortcuts;
+
+const CONFIG_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+fn modified_time(filepath: &Path) -> Option<SystemTime> {
+    fs::metadata(filepath)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+//* Watches config.json and applies changes to it without a restart, including the ones made by the settings window */
+pub fn watch_config(app: AppHandle) {
+    let filepath = match config_filepath() {
+        Some(filepath) => filepath,
+        None => {
+            eprintln!("Unable to locate the config file, changes to it won't be applied");
+            return;
+        }
+    };
+    let mut applied = match app.state::<AppState>().config.lock() {
+        Ok(config) => config.clone(),
+        Err(e) => {
+            eprintln!(
+                "Unable to read the config, changes to it won't be applied: {}",
+                e
+            );
+            return;
+        }
+    };
+
+    thread::spawn(move || {
+        let mut last_modified = modified_time(&filepath);
+        loop {
+            thread::sleep(CONFIG_POLL_INTERVAL);
+            let modified = modified_time(&filepath);
+            if modified == last_modified {
+                continue;
+            }
+            last_modified = modified;
+
+            // a file that can't be used leaves the running config as it is
+            let settings = match AppConfig::reload() {
+                Ok(settings) if settings.errors.is_empty() => {
+                    let mut errors = vec![];
+                    if settings.config != applied {
+                        println!("config.json changed, applying the new settings");
+                        errors = apply_config(&app, &applied, &settings.config);
+                        let previous_launch_shortcut = applied.launch_shortcut;
+                        applied = settings.config;
+                        // the previous launch shortcut stays registered when the new one can't be
+                        if errors.iter().any(|error| error.field == "launch_shortcut") {
+                            applied.launch_shortcut = previous_launch_shortcut;
+                        }
+                    }
+                    SettingsState {
+                        config: applied.clone(),
+                        errors,
+                    }
+                }
+                Ok(settings) => SettingsState {
+                    config: applied.clone(),
+                    errors: settings.errors,
+                },
+                Err(error) => SettingsState {
+                    config: applied.clone(),
+                    errors: vec![error],
+                },
+            };
+            for error in settings.errors.iter() {
+                eprintln!("Config error in '{}': {}", error.field, error.message);
+            }
+
+            let state = app.state::<AppState>();
+            if let Ok(mut config) = state.config.lock() {
+                *config = settings.config.clone();
+            }
+            if let Ok(mut config_errors) = state.config_errors.lock() {
+                *config_errors = settings.errors.clone();
+            }
+            app.emit(
+                to_variant_name(&SFEvent::SettingsChanged).unwrap(),
+                settings,
+            )
+            .ok();
+        }
+    });
+}
+
+// browser history settings aren't handled here, the history sync reads them
+// again before every sync
+fn apply_config(app: &AppHandle, previous: &AppConfig, config: &AppConfig) -> Vec<ConfigError> {
+    let mut errors = vec![];
+    if previous.launch_shortcut != config.launch_shortcut
+        || previous.keybindings != config.keybindings
+    {
+        errors.extend(replace_shortcuts(app, previous, config));
+    }
+
+    let previous_directories = previous.get_search_directories().unwrap_or_default();
+    let directories = config.get_search_directories().unwrap_or_default();
+    if previous_directories != directories {
+        // directories whose rules changed show up in both lists
+        let (unchanged, added): (Vec<SearchDirectory>, Vec<SearchDirectory>) = directories
+            .into_iter()
+            .partition(|dir| previous_directories.contains(dir));
+        let removed: Vec<SearchDirectory> = previous_directories
+            .into_iter()
+            .filter(|dir| !unchanged.contains(dir))
+            .collect();
+        // walking new directories can take a while, so it happens off the watcher thread
+        thread::spawn(move || {
+            if let Err(e) =
+                FileDataSource::new("sf_cache").reindex_directories(&added, &removed, &unchanged)
+            {
+                eprintln!("Error reindexing the search directories: {:?}", e);
+            }
+        });
+    }
+    errors
+}
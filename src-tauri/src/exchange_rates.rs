@@ -0,0 +1,204 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use serde::{Deserialize, Serialize};
+use swordfish_utilities::get_cache_path;
+use thiserror::Error;
+use tokio::runtime::Handle;
+
+use crate::settings::AppConfig;
+
+// the rates most sources publish change once a day
+const REFRESH_INTERVAL: Duration = Duration::from_secs(60 * 60 * 12);
+// how long a failed fetch isn't tried again, unless the url changes
+const RETRY_INTERVAL: Duration = Duration::from_secs(60 * 30);
+// how soon a different url in the settings is fetched from
+const URL_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Error, Debug)]
+pub enum ExchangeRateError {
+    #[error("unable to fetch exchange rates: {0}")]
+    Fetch(#[from] reqwest::Error),
+    #[error("the exchange rates don't include a rate for USD")]
+    MissingUsd,
+    #[error("the exchange rate for {0} is not a positive number")]
+    InvalidRate(String),
+}
+
+//* Rates of currencies relative to `base`, the format of the Frankfurter API: {"base": "EUR", "date": "2024-08-23", "rates": {"USD": 1.1187}} */
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ExchangeRates {
+    pub base: String,
+    /* the day the rates were published */
+    pub date: String,
+    pub rates: HashMap<String, f64>,
+}
+
+impl ExchangeRates {
+    //* How many units of `currency` a US dollar is worth, fend converts every currency through the dollar */
+    pub fn per_us_dollar(&self, currency: &str) -> Option<f64> {
+        let rate = |code: &str| {
+            if code == self.base {
+                Some(1.0)
+            } else {
+                self.rates.get(code).copied()
+            }
+        };
+        Some(rate(&currency.to_uppercase())? / rate("USD")?)
+    }
+
+    fn validate(self) -> Result<Self, ExchangeRateError> {
+        if self.per_us_dollar("USD").is_none() {
+            return Err(ExchangeRateError::MissingUsd);
+        }
+        match self
+            .rates
+            .iter()
+            .find(|(_, rate)| !rate.is_finite() || **rate <= 0.0)
+        {
+            Some((currency, _)) => Err(ExchangeRateError::InvalidRate(currency.clone())),
+            None => Ok(self),
+        }
+    }
+}
+
+// what's written to exchange_rates.json, caches written before the url was stored
+// don't have one, so they're fetched again
+#[derive(Serialize, Deserialize)]
+struct CachedRates {
+    #[serde(default)]
+    source: Option<String>,
+    #[serde(flatten)]
+    rates: ExchangeRates,
+}
+
+fn cache_filepath() -> Option<PathBuf> {
+    get_cache_path().map(|mut dir| {
+        dir.push("exchange_rates.json");
+        dir
+    })
+}
+
+fn read_cache() -> Option<CachedRates> {
+    let contents = fs::read_to_string(cache_filepath()?).ok()?;
+    match serde_json::from_str::<CachedRates>(&contents)
+        .map_err(|e| e.to_string())
+        .and_then(|cached| {
+            Ok(CachedRates {
+                rates: cached.rates.validate().map_err(|e| e.to_string())?,
+                ..cached
+            })
+        }) {
+        Ok(cached) => Some(cached),
+        Err(e) => {
+            eprintln!("Ignoring the cached exchange rates: {}", e);
+            None
+        }
+    }
+}
+
+fn write_cache(cached: &CachedRates) {
+    let filepath = match cache_filepath() {
+        Some(filepath) => filepath,
+        None => return,
+    };
+    match serde_json::to_string_pretty(cached) {
+        Ok(contents) => {
+            if let Err(e) = fs::write(filepath, contents) {
+                eprintln!("Error writing the exchange rates cache: {}", e);
+            }
+        }
+        Err(e) => eprintln!("Error serializing the exchange rates: {}", e),
+    }
+}
+
+// rates are fetched again once the cache is older than the refresh interval
+fn cache_is_stale() -> bool {
+    cache_filepath()
+        .and_then(|filepath| fs::metadata(filepath).ok())
+        .and_then(|metadata| metadata.modified().ok())
+        .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+        .map_or(true, |age| age > REFRESH_INTERVAL)
+}
+
+pub async fn fetch_exchange_rates(url: &str) -> Result<ExchangeRates, ExchangeRateError> {
+    reqwest::Client::builder()
+        .timeout(FETCH_TIMEOUT)
+        .build()?
+        .get(url)
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<ExchangeRates>()
+        .await?
+        .validate()
+}
+
+//* The exchange rates the calculator converts currencies with, shared between the calculator and the thread refreshing them */
+#[derive(Clone, Default)]
+pub struct ExchangeRateSource {
+    rates: Arc<RwLock<Option<Arc<ExchangeRates>>>>,
+}
+
+impl ExchangeRateSource {
+    //* Starts out with the cached rates, so conversions work offline */
+    pub fn new() -> Self {
+        Self {
+            rates: Arc::new(RwLock::new(
+                read_cache().map(|cached| Arc::new(cached.rates)),
+            )),
+        }
+    }
+
+    pub fn current(&self) -> Option<Arc<ExchangeRates>> {
+        self.rates.read().ok().and_then(|rates| rates.clone())
+    }
+
+    fn replace(&self, rates: ExchangeRates, url: &str) {
+        let cached = CachedRates {
+            source: Some(url.to_string()),
+            rates,
+        };
+        write_cache(&cached);
+        if let Ok(mut current) = self.rates.write() {
+            *current = Some(Arc::new(cached.rates));
+        }
+    }
+
+    //* Fetches new rates from the url in the settings whenever the cached ones are out of date or came from a different url. Failing to fetch them, e.g. while offline, keeps the cached ones. */
+    pub fn start_background_refresh(&self) {
+        let source = self.clone();
+        let runtime = Handle::current();
+        thread::spawn(move || {
+            let mut fetched_from = read_cache().and_then(|cached| cached.source);
+            let mut last_attempt: Option<(String, Instant)> = None;
+            loop {
+                let url = AppConfig::read()
+                    .unwrap_or_default()
+                    .calculator
+                    .exchange_rates_url;
+                let out_of_date = fetched_from.as_ref() != Some(&url) || cache_is_stale();
+                let attempt_due = last_attempt.as_ref().map_or(true, |(attempted, at)| {
+                    *attempted != url || at.elapsed() > RETRY_INTERVAL
+                });
+                if out_of_date && attempt_due {
+                    last_attempt = Some((url.clone(), Instant::now()));
+                    match runtime.block_on(fetch_exchange_rates(&url)) {
+                        Ok(rates) => {
+                            println!("Fetched exchange rates from {}", rates.date);
+                            source.replace(rates, &url);
+                            fetched_from = Some(url);
+                        }
+                        Err(e) => eprintln!("{}", e),
+                    }
+                }
+                thread::sleep(URL_CHECK_INTERVAL);
+            }
+        });
+    }
+}
@@ -1,17 +1,25 @@
-use crate::settings::AppConfig;
+use crate::query_parser::parse_query;
+use crate::settings::{AppConfig, SearchDirectory};
 use crate::sqlite::SQLite;
 use crate::utilities::cache_all_app_icons;
 use chrono::prelude::DateTime;
 use chrono::Utc;
 use fuzzy_matcher::skim::SkimMatcherV2;
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
 use ignore::WalkBuilder;
 use rayon::prelude::*;
-use rusqlite::{params, Result};
+use rusqlite::{params, Params, Result, Transaction};
 use std::cmp;
-use std::path::Path;
+use std::collections::HashMap;
+use std::ffi::{OsStr, OsString};
+use std::fs::{self, Metadata};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::mpsc::{self, Sender};
 use std::sync::{Arc, Mutex};
-use std::{ffi::OsString, time::Instant};
-use swordfish_types::{DataSource, FileInfo, Query};
+use std::thread;
+use std::time::{Duration, Instant};
+use swordfish_types::{unix_timestamp, DataSource, FileInfo, FileType, Query, QueryFilter};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -26,25 +34,30 @@ pub enum DSError {
     TransactionError(#[from] rusqlite::Error),
 }
 
-pub fn score_files(query: &Query, directories: Vec<String>) -> Option<Vec<String>> {
-    let search_string = query.search_string.clone();
+// apps are bumped above everything because they're the most likely to be searched
+// for, folders a bit less so they show up above the files inside them
+const APP_BONUS: i64 = 10;
+const DIRECTORY_BONUS: i64 = 5;
+
+pub fn score_files(search_string: &str, files: Vec<FileInfo>) -> Option<Vec<FileInfo>> {
     let start = Instant::now();
     let matcher = SkimMatcherV2::default();
-    let mut scored_directories: Vec<(i64, String)> = directories
-        .par_iter()
-        .map(|directory| {
-            let path = Path::new(directory);
+    let mut scored_files: Vec<(i64, FileInfo)> = files
+        .into_par_iter()
+        .map(|file| {
+            let path = Path::new(&file.path);
             let is_app = path.extension() == Some(&OsString::from("app"));
             let mut score = matcher
-                .fuzzy(directory, &search_string, true)
+                .fuzzy(&file.path, search_string, true)
                 .map(|res| res.0)
                 .unwrap_or(0);
 
+            let mut fname_score = 0;
             if let Some(fname) = path.file_name() {
-                let fname_score = matcher
+                fname_score = matcher
                     .fuzzy(
                         fname.to_string_lossy().to_string().as_str(),
-                        &search_string,
+                        search_string,
                         true,
                     )
                     .map(|res| res.0)
@@ -53,110 +66,358 @@ pub fn score_files(query: &Query, directories: Vec<String>) -> Option<Vec<String
             }
 
             if is_app && score > 0 {
-                // bumping app matches because they're more likely to be relevant
-                score += 10;
+                score += APP_BONUS;
+            } else if file.file_type == FileType::Directory && fname_score > 0 {
+                // only when the folder's own name matches, not just a parent of it
+                score += DIRECTORY_BONUS;
             }
 
-            (score, directory.to_owned())
+            (score, file)
         })
         .filter(|res| res.0 > 0)
         .collect();
     println!("finished search in {}ms", start.elapsed().as_millis());
 
-    scored_directories.sort_by(|a, b| b.0.cmp(&a.0));
-    Some(scored_directories.iter().map(|res| res.1.clone()).collect())
+    scored_files.sort_by(|a, b| b.0.cmp(&a.0));
+    Some(scored_files.into_iter().map(|res| res.1).collect())
+}
+
+//* A path in the file index, with the metadata it had when it was indexed */
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexedPath {
+    pub path: String,
+    pub is_directory: bool,
+    pub file_type: FileType,
+    pub extension: Option<String>,
+    pub size: u64,
+    /* unix timestamps in seconds */
+    pub last_modified: Option<i64>,
+    pub created: Option<i64>,
+}
+
+impl IndexedPath {
+    fn new(path: &Path, is_directory: bool, metadata: Option<Metadata>) -> Self {
+        let extension = path.extension().and_then(OsStr::to_str).map(str::to_string);
+        let file_type = if is_directory {
+            FileType::Directory
+        } else {
+            FileType::from_str(&path.to_string_lossy()).unwrap_or(FileType::File)
+        };
+        Self {
+            path: path.to_string_lossy().to_string(),
+            is_directory,
+            file_type,
+            extension,
+            size: metadata.as_ref().map_or(0, Metadata::len),
+            last_modified: metadata
+                .as_ref()
+                .and_then(|metadata| metadata.modified().ok().map(unix_timestamp)),
+            created: metadata
+                .as_ref()
+                .and_then(|metadata| metadata.created().ok().map(unix_timestamp)),
+        }
+    }
+
+    pub fn into_file_info(self) -> FileInfo {
+        FileInfo {
+            file_name: Path::new(&self.path)
+                .file_stem()
+                .and_then(OsStr::to_str)
+                .map(str::to_string),
+            extension: self.extension,
+            file_type: self.file_type,
+            size: self.size,
+            last_modified: self.last_modified,
+            created: self.created,
+            path: self.path,
+        }
+    }
+}
+
+// globs without a "/" match at any depth, like in a .gitignore
+fn build_glob_set(patterns: &[String]) -> GlobSet {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns.iter() {
+        let pattern = pattern.trim_end_matches('/');
+        let anchored = if pattern.contains('/') {
+            pattern.to_string()
+        } else {
+            format!("**/{}", pattern)
+        };
+        match GlobBuilder::new(&anchored).literal_separator(true).build() {
+            Ok(glob) => {
+                builder.add(glob);
+            }
+            Err(e) => eprintln!("Ignoring search directory glob '{}': {}", pattern, e),
+        }
+    }
+    builder.build().unwrap_or_else(|e| {
+        eprintln!("Error building the search directory globs: {}", e);
+        GlobSet::empty()
+    })
+}
+
+//* A search directory's rules, compiled once per walk */
+struct DirectoryRules {
+    root: PathBuf,
+    include: GlobSet,
+    exclude: GlobSet,
+    file_types: Vec<FileType>,
+}
+
+impl DirectoryRules {
+    fn new(directory: &SearchDirectory) -> Self {
+        Self {
+            root: PathBuf::from(&directory.path),
+            include: build_glob_set(&directory.include),
+            exclude: build_glob_set(&directory.exclude),
+            file_types: directory.file_types.clone(),
+        }
+    }
+
+    fn relative<'a>(&self, path: &'a Path) -> &'a Path {
+        path.strip_prefix(&self.root).unwrap_or(path)
+    }
+
+    fn is_excluded(&self, path: &Path) -> bool {
+        self.exclude.is_match(self.relative(path))
+    }
+
+    fn is_included(&self, path: &Path, file_type: &FileType) -> bool {
+        (self.include.is_empty() || self.include.is_match(self.relative(path)))
+            && (self.file_types.is_empty() || self.file_types.contains(file_type))
+    }
+}
+
+// Symlinks into a search directory would show up twice, under their own path and
+// their target's. The others are indexed as what they point to, e.g. Safari.app
+// in /Applications links to a bundle on the sealed system volume.
+fn symlink_target_metadata(path: &Path, roots: &[PathBuf]) -> Option<Metadata> {
+    let target = fs::canonicalize(path).ok()?;
+    if roots.iter().any(|root| target.starts_with(root)) {
+        return None;
+    }
+    fs::metadata(target).ok()
+}
+
+fn walk_search_directory(
+    directory: &SearchDirectory,
+    roots: &Arc<Vec<PathBuf>>,
+    paths: &Arc<Mutex<Vec<IndexedPath>>>,
+) {
+    let rules = Arc::new(DirectoryRules::new(directory));
+    WalkBuilder::new(&directory.path)
+        .threads(cmp::min(4, num_cpus::get()))
+        .hidden(!directory.include_hidden)
+        .git_ignore(directory.respect_gitignore)
+        .git_global(directory.respect_gitignore)
+        .git_exclude(directory.respect_gitignore)
+        .max_depth(directory.max_depth)
+        .build_parallel()
+        .run(|| {
+            let paths = Arc::clone(paths);
+            let rules = Arc::clone(&rules);
+            let roots = Arc::clone(roots);
+            Box::new(move |entry| {
+                use ignore::WalkState;
+
+                let entry = match entry {
+                    Ok(entry) => entry,
+                    Err(_) => return WalkState::Continue,
+                };
+                // the search directory itself isn't a result
+                if entry.depth() == 0 {
+                    return WalkState::Continue;
+                }
+                let file_type = match entry.file_type() {
+                    Some(file_type) => file_type,
+                    None => return WalkState::Continue,
+                };
+                let path = entry.path();
+                let metadata = if file_type.is_symlink() {
+                    match symlink_target_metadata(path, &roots) {
+                        Some(metadata) => Some(metadata),
+                        None => return WalkState::Continue,
+                    }
+                } else {
+                    entry.metadata().ok()
+                };
+                let is_dir = metadata
+                    .as_ref()
+                    .map_or(file_type.is_dir(), Metadata::is_dir);
+                if rules.is_excluded(path) {
+                    return if is_dir {
+                        WalkState::Skip
+                    } else {
+                        WalkState::Continue
+                    };
+                }
+
+                // app bundles are indexed as applications, never their contents
+                let is_app_bundle = is_dir && path.extension() == Some(&OsString::from("app"));
+                let indexed = IndexedPath::new(path, is_dir && !is_app_bundle, metadata);
+                if rules.is_included(path, &indexed.file_type) {
+                    if let Ok(mut paths) = paths.lock() {
+                        paths.push(indexed);
+                    }
+                }
+                if is_app_bundle {
+                    WalkState::Skip
+                } else {
+                    WalkState::Continue
+                }
+            })
+        });
+}
+
+// `indexed` are the directories whose paths are already in the index and aren't walked again
+fn walk_search_directories(
+    directories: &[SearchDirectory],
+    indexed: &[SearchDirectory],
+) -> Vec<IndexedPath> {
+    let paths = Arc::new(Mutex::new(Vec::new()));
+    let roots = Arc::new(
+        directories
+            .iter()
+            .chain(indexed)
+            .filter_map(|directory| fs::canonicalize(&directory.path).ok())
+            .collect::<Vec<_>>(),
+    );
+    // each directory has its own walker since their depth and ignore rules differ
+    for directory in directories.iter() {
+        walk_search_directory(directory, &roots, &paths);
+    }
+    paths.lock().map(|paths| paths.clone()).unwrap_or_default()
 }
 
 pub struct FileDataSource {
     sqlite: SQLite,
     name: String,
+    /* results are sent here to be checked for files deleted since they were indexed */
+    stale_check: Sender<Vec<String>>,
+}
+
+// columns added to the table after it was first created
+const INDEX_COLUMNS: &[(&str, &str)] = &[
+    ("is_directory", "INTEGER NOT NULL DEFAULT 0"),
+    ("file_type", "TEXT"),
+    ("extension", "TEXT"),
+    ("size", "INTEGER NOT NULL DEFAULT 0"),
+    ("last_modified", "INTEGER"),
+    ("created", "INTEGER"),
+];
+
+// how long a path that was found to exist isn't checked again
+const STALE_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+// FileType is stored under the same name it's serialized with
+fn file_type_to_sql(file_type: &FileType) -> Option<String> {
+    serde_json::to_value(file_type)
+        .ok()
+        .and_then(|value| value.as_str().map(str::to_string))
+}
+
+fn file_type_from_sql(file_type: Option<String>, path: &str) -> FileType {
+    file_type
+        .and_then(|file_type| serde_json::from_value(serde_json::Value::String(file_type)).ok())
+        .unwrap_or_else(|| FileType::from_str(path).unwrap_or(FileType::File))
+}
+
+//* Deletes the rows of paths that no longer exist, off the query path so results never wait on the file system */
+fn start_stale_check(name: &str) -> Sender<Vec<String>> {
+    let (sender, receiver) = mpsc::channel::<Vec<String>>();
+    let name = name.to_string();
+    thread::spawn(move || {
+        let sqlite = match SQLite::new(&name, false) {
+            Ok(sqlite) => sqlite,
+            Err(e) => {
+                eprintln!("Unable to prune the file search cache: {}", e);
+                return;
+            }
+        };
+        let mut checked: HashMap<String, Instant> = HashMap::new();
+        // ends once the data source is dropped
+        while let Ok(paths) = receiver.recv() {
+            checked.retain(|_, at| at.elapsed() < STALE_CHECK_INTERVAL);
+            for path in paths {
+                if checked.contains_key(&path) {
+                    continue;
+                }
+                if Path::new(&path).exists() {
+                    checked.insert(path, Instant::now());
+                } else if let Err(e) = sqlite
+                    .conn
+                    .execute(&format!("DELETE FROM {} WHERE path = ?1", name), [&path])
+                {
+                    eprintln!(
+                        "Unable to remove '{}' from the file search cache: {}",
+                        path, e
+                    );
+                }
+            }
+        }
+    });
+    sender
 }
 
 impl FileDataSource {
-    pub fn read(&self) -> Option<Vec<String>> {
-        let query = format!("SELECT path FROM {}", self.name);
+    pub fn read(&self) -> Option<Vec<IndexedPath>> {
+        self.read_where("1", [])
+    }
+
+    //* Only the applications, cheap enough to not hold the index up like reading every path does */
+    pub fn read_applications(&self) -> Option<Vec<IndexedPath>> {
+        let application = file_type_to_sql(&FileType::Application)?;
+        // rows indexed before file types were stored only have their path to go by
+        let paths = self.read_where(
+            "file_type = ?1 OR (file_type IS NULL AND (path LIKE '%.app' OR path LIKE '%.exe'))",
+            [application],
+        )?;
+        Some(
+            paths
+                .into_iter()
+                .filter(|path| path.file_type == FileType::Application)
+                .collect(),
+        )
+    }
+
+    fn read_where<P: Params>(&self, condition: &str, params: P) -> Option<Vec<IndexedPath>> {
+        let query = format!(
+            "SELECT path, is_directory, file_type, extension, size, last_modified, created FROM {} WHERE {}",
+            self.name, condition
+        );
         let mut stmt = self.sqlite.conn.prepare(&query).ok()?;
-        let rows = stmt.query_map([], |row| row.get::<_, String>(0)).ok()?;
+        let rows = stmt
+            .query_map(params, |row| {
+                let path: String = row.get(0)?;
+                Ok(IndexedPath {
+                    file_type: file_type_from_sql(row.get(2)?, &path),
+                    path,
+                    is_directory: row.get(1)?,
+                    extension: row.get(3)?,
+                    size: row.get(4)?,
+                    last_modified: row.get(5)?,
+                    created: row.get(6)?,
+                })
+            })
+            .ok()?;
         Some(rows.filter_map(Result::ok).collect())
     }
 
     pub fn cache_file_search_paths(&mut self) -> Result<(), DSError> {
         println!("Starting to cache application paths...");
         let start = Instant::now();
-        let paths = Arc::new(Mutex::new(Vec::new()));
-
-        if let Some(directories) = AppConfig::new().get_search_directories() {
-            let mut walker = WalkBuilder::new(directories.first().unwrap());
-            directories
-                .iter()
-                .skip(1)
-                .fold(&mut walker, |builder, dir| builder.add(dir))
-                .threads(cmp::min(4, num_cpus::get()))
-                .hidden(false)
-                .max_depth(Some(6))
-                .build_parallel()
-                .run(|| {
-                    let paths = Arc::clone(&paths);
-                    Box::new(move |entry| {
-                        use ignore::WalkState;
-
-                        if let Ok(entry) = entry {
-                            let path = entry.path().to_owned();
-
-                            #[cfg(target_os = "macos")]
-                            {
-                                if path.extension().map_or(false, |ext| ext == "app") {
-                                    paths.lock().unwrap().push(path);
-                                    return WalkState::Continue;
-                                }
-                                let path_str = path.to_string_lossy();
-                                if path_str.ends_with("/Contents")
-                                    || path_str.contains("Native Instruments")
-                                    || path_str.contains("Adobe Creative Cloud")
-                                {
-                                    return WalkState::Skip;
-                                }
-                                if !path.is_dir() && !path.is_symlink() && path.is_absolute() {
-                                    paths.lock().unwrap().push(path);
-                                    return WalkState::Continue;
-                                }
-                            }
-
-                            #[cfg(target_os = "windows")]
-                            {
-                                if path.extension().map_or(false, |ext| ext == "exe") {
-                                    paths.lock().unwrap().push(path);
-                                }
-                            }
-
-                            // this probably doesn't work? AI wrote it. /shrug.
-                            #[cfg(target_os = "linux")]
-                            {
-                                if path.extension().map_or(false, |ext| ext == "desktop") {
-                                    paths.lock().unwrap().push(path);
-                                }
-                            }
-                        }
-                        WalkState::Continue
-                    })
-                });
 
-            let paths = paths.lock().unwrap();
+        // an unreadable config leaves the index as it is instead of reindexing the defaults
+        if let Some(directories) =
+            AppConfig::read().and_then(|config| config.get_search_directories())
+        {
+            let paths = walk_search_directories(&directories, &[]);
 
             // Clear existing entries and insert new ones (this is a complete reindex)
             let transaction_handle = self.sqlite.conn.transaction()?;
             transaction_handle.execute(&format!("DELETE FROM {}", self.name), [])?;
-            let utc: DateTime<Utc> = Utc::now();
-
-            for path in paths.iter() {
-                transaction_handle.execute(
-                    &format!(
-                        "INSERT OR IGNORE INTO {} (path, last_updated) VALUES (?1, ?2)",
-                        self.name
-                    ),
-                    params![path.to_string_lossy().to_string(), format!("{:?}", utc)],
-                )?;
-            }
+            insert_paths(&transaction_handle, &self.name, &paths)?;
             if let Err(error) = transaction_handle.commit() {
                 Err(DSError::TransactionError(error))
             } else {
@@ -172,6 +433,112 @@ impl FileDataSource {
             Err(DSError::MissingSearchDir)
         }
     }
+
+    //* Fuzzy matches `term` against the files that pass every filter, or lists the most recently modified ones when there's only filters */
+    pub fn query_files(&self, term: &str, filters: &[QueryFilter]) -> Option<Vec<FileInfo>> {
+        self.query_paths(self.read()?, term, filters)
+    }
+
+    //* Like `query_files`, but only looks through the applications */
+    pub fn query_applications(&self, term: &str, filters: &[QueryFilter]) -> Option<Vec<FileInfo>> {
+        self.query_paths(self.read_applications()?, term, filters)
+    }
+
+    fn query_paths(
+        &self,
+        paths: Vec<IndexedPath>,
+        term: &str,
+        filters: &[QueryFilter],
+    ) -> Option<Vec<FileInfo>> {
+        let files: Vec<FileInfo> = paths
+            .into_iter()
+            .map(IndexedPath::into_file_info)
+            .filter(|file| filters.iter().all(|filter| filter.matches(file)))
+            .collect();
+        let files = if term.trim().is_empty() {
+            let mut files = files;
+            files.sort_by(|a, b| b.last_modified.cmp(&a.last_modified));
+            files
+        } else {
+            score_files(term, files)?
+        };
+
+        let results: Vec<FileInfo> = files.into_iter().take(50).collect();
+        self.stale_check
+            .send(results.iter().map(|file| file.path.clone()).collect())
+            .ok();
+        Some(results)
+    }
+
+    //* Indexes directories added to the settings and drops the paths of removed ones, leaving the rest of the cache alone. A directory whose rules changed is both removed and added. */
+    pub fn reindex_directories(
+        &mut self,
+        added: &[SearchDirectory],
+        removed: &[SearchDirectory],
+        unchanged: &[SearchDirectory],
+    ) -> Result<(), DSError> {
+        let start = Instant::now();
+        let paths = walk_search_directories(added, unchanged);
+
+        // a path is kept if an unchanged directory still contains it
+        let stale_paths: Vec<String> = self
+            .read()
+            .unwrap_or_default()
+            .into_iter()
+            .map(|entry| entry.path)
+            .filter(|path| {
+                let path = Path::new(path);
+                removed.iter().any(|dir| path.starts_with(&dir.path))
+                    && !unchanged.iter().any(|dir| path.starts_with(&dir.path))
+            })
+            .collect();
+
+        let transaction_handle = self.sqlite.conn.transaction()?;
+        for path in stale_paths.iter() {
+            transaction_handle.execute(
+                &format!("DELETE FROM {} WHERE path = ?1", self.name),
+                [path],
+            )?;
+        }
+        insert_paths(&transaction_handle, &self.name, &paths)?;
+        transaction_handle.commit()?;
+
+        println!(
+            "Reindexed search directories in {}ms: {} paths added, {} removed",
+            start.elapsed().as_millis(),
+            paths.len(),
+            stale_paths.len()
+        );
+        Ok(())
+    }
+}
+
+fn insert_paths(
+    transaction_handle: &Transaction,
+    name: &str,
+    paths: &[IndexedPath],
+) -> Result<(), DSError> {
+    let utc: DateTime<Utc> = Utc::now();
+    for entry in paths.iter() {
+        transaction_handle.execute(
+            &format!(
+                "INSERT OR REPLACE INTO {} (path, is_directory, file_type, extension, size, last_modified, created, last_updated)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                name
+            ),
+            params![
+                entry.path,
+                entry.is_directory,
+                file_type_to_sql(&entry.file_type),
+                entry.extension,
+                entry.size,
+                entry.last_modified,
+                entry.created,
+                format!("{:?}", utc)
+            ],
+        )?;
+    }
+    Ok(())
 }
 
 impl DataSource<Vec<FileInfo>> for FileDataSource {
@@ -181,6 +548,12 @@ impl DataSource<Vec<FileInfo>> for FileDataSource {
                 "CREATE TABLE IF NOT EXISTS {} (
               id INTEGER PRIMARY KEY,
               path TEXT NOT NULL UNIQUE,
+              is_directory INTEGER NOT NULL DEFAULT 0,
+              file_type TEXT,
+              extension TEXT,
+              size INTEGER NOT NULL DEFAULT 0,
+              last_modified INTEGER,
+              created INTEGER,
               last_updated TEXT NOT NULL
             )",
                 name
@@ -191,9 +564,30 @@ impl DataSource<Vec<FileInfo>> for FileDataSource {
                     name, e
                 )
             };
+            // caches made by older versions, the next reindex fills the new columns in
+            for (column, definition) in INDEX_COLUMNS.iter() {
+                sqlite
+                    .conn
+                    .execute(
+                        &format!("ALTER TABLE {} ADD COLUMN {} {}", name, column, definition),
+                        [],
+                    )
+                    .ok();
+            }
+            // applications are looked up on their own on every query
+            if let Err(e) = sqlite.conn.execute(
+                &format!(
+                    "CREATE INDEX IF NOT EXISTS {}_file_type ON {} (file_type)",
+                    name, name
+                ),
+                [],
+            ) {
+                eprintln!("Unable to index the file types of '{}': {}", name, e);
+            }
             Self {
                 sqlite,
                 name: name.to_string(),
+                stale_check: start_stale_check(name),
             }
         } else {
             panic!("Error initializing the FileDataSource")
@@ -208,15 +602,7 @@ impl DataSource<Vec<FileInfo>> for FileDataSource {
     }
 
     fn query(&self, query: &Query) -> Option<Vec<FileInfo>> {
-        match self.read() {
-            None => None,
-            Some(dirs) => score_files(query, dirs).and_then(|filepaths| {
-                filepaths
-                    .iter()
-                    .map(|filepath| FileInfo::from_string(filepath.clone()))
-                    .take(50)
-                    .collect()
-            }),
-        }
+        let parsed = parse_query(&query.search_string);
+        self.query_files(&parsed.term, &parsed.filters)
     }
 }
@@ -0,0 +1,240 @@
+// Frecency works like Firefox's: the most recent visits of a page are sampled,
+// each one is worth points depending on how long ago it happened and how the
+// page was reached, and the average is scaled by the total visit count. The
+// buckets already account for age, so scores only decay daily once the newest
+// visit is older than the last bucket, which keeps old pages sinking over time.
+
+const SECONDS_PER_DAY: i64 = 60 * 60 * 24;
+
+//* How many of a page's most recent visits are scored */
+pub const MAX_SAMPLED_VISITS: usize = 10;
+
+// (maximum age in days, points)
+const RECENCY_BUCKETS: [(i64, f64); 4] = [(4, 100.0), (14, 70.0), (31, 50.0), (90, 30.0)];
+const OLD_VISIT_POINTS: f64 = 10.0;
+const DAILY_DECAY: f64 = 0.975;
+
+//* How a page was reached, as far as the browser records it */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VisitKind {
+    Link,
+    Typed,
+    Bookmark,
+    /* redirects, reloads, embedded frames, ... */
+    Other,
+}
+
+impl VisitKind {
+    pub fn from_i64(kind: i64) -> Self {
+        match kind {
+            1 => Self::Typed,
+            2 => Self::Bookmark,
+            3 => Self::Other,
+            _ => Self::Link,
+        }
+    }
+
+    pub fn to_i64(&self) -> i64 {
+        match self {
+            Self::Link => 0,
+            Self::Typed => 1,
+            Self::Bookmark => 2,
+            Self::Other => 3,
+        }
+    }
+
+    // typing a url shows much more intent than following a link to it
+    fn bonus(&self) -> f64 {
+        match self {
+            Self::Typed => 2.0,
+            Self::Link => 1.0,
+            Self::Bookmark => 0.75,
+            Self::Other => 0.25,
+        }
+    }
+}
+
+//* A single visit to a page */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Visit {
+    /* timestamp in seconds */
+    pub time: i64,
+    pub kind: VisitKind,
+}
+
+fn age_in_days(time: i64, now: i64) -> i64 {
+    // visits from the future (clock changes, synced devices) count as just now
+    (now - time).max(0) / SECONDS_PER_DAY
+}
+
+fn recency_points(age_in_days: i64) -> f64 {
+    RECENCY_BUCKETS
+        .iter()
+        .find(|(max_age, _)| age_in_days <= *max_age)
+        .map_or(OLD_VISIT_POINTS, |(_, points)| *points)
+}
+
+//* Scores a page from its visits, `now` is a timestamp in seconds */
+pub fn calculate_frecency(
+    visit_count: i64,
+    last_visit_time: i64,
+    visits: &[Visit],
+    now: i64,
+) -> f64 {
+    let mut sampled: Vec<Visit> = visits.to_vec();
+    sampled.sort_by(|a, b| b.time.cmp(&a.time));
+    sampled.truncate(MAX_SAMPLED_VISITS);
+    // browsers without per-visit data still tell us when the page was last visited
+    if sampled.is_empty() {
+        sampled.push(Visit {
+            time: last_visit_time,
+            kind: VisitKind::Link,
+        });
+    }
+
+    let points: f64 = sampled
+        .iter()
+        .map(|visit| recency_points(age_in_days(visit.time, now)) * visit.kind.bonus())
+        .sum();
+    let average_points = points / sampled.len() as f64;
+    let visit_count = visit_count.max(sampled.len() as i64) as f64;
+
+    let newest_visit = sampled[0].time.max(last_visit_time);
+    let oldest_bucket = RECENCY_BUCKETS[RECENCY_BUCKETS.len() - 1].0;
+    let days_past_buckets = (age_in_days(newest_visit, now) - oldest_bucket).max(0);
+    let decay = DAILY_DECAY.powi(days_past_buckets as i32);
+
+    average_points * visit_count * decay
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: i64 = 1_700_000_000;
+
+    fn days_ago(days: i64) -> i64 {
+        NOW - days * SECONDS_PER_DAY
+    }
+
+    fn visits(kind: VisitKind, ages_in_days: &[i64]) -> Vec<Visit> {
+        ages_in_days
+            .iter()
+            .map(|days| Visit {
+                time: days_ago(*days),
+                kind,
+            })
+            .collect()
+    }
+
+    fn single_link(age_in_days: i64) -> f64 {
+        let visits = visits(VisitKind::Link, &[age_in_days]);
+        calculate_frecency(1, visits[0].time, &visits, NOW)
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-9,
+            "expected {}, got {}",
+            expected,
+            actual
+        );
+    }
+
+    #[test]
+    fn recency_buckets() {
+        for (age, points) in [
+            (0, 100.0),
+            (4, 100.0),
+            (5, 70.0),
+            (14, 70.0),
+            (15, 50.0),
+            (31, 50.0),
+            (32, 30.0),
+            (90, 30.0),
+            (91, OLD_VISIT_POINTS * DAILY_DECAY),
+        ] {
+            assert_close(single_link(age), points);
+        }
+    }
+
+    // within the buckets a visit is only worth its bucket's points, decay on
+    // top of that would count its age twice
+    #[test]
+    fn age_is_not_counted_twice() {
+        assert_close(single_link(20) / single_link(0), 50.0 / 100.0);
+        assert_close(single_link(60) / single_link(10), 30.0 / 70.0);
+        assert_close(single_link(3), single_link(0));
+    }
+
+    #[test]
+    fn recent_visits_beat_old_ones_at_the_same_visit_count() {
+        let recent = visits(VisitKind::Link, &[1, 2, 3]);
+        let old = visits(VisitKind::Link, &[40, 41, 42]);
+        assert!(
+            calculate_frecency(5, recent[0].time, &recent, NOW)
+                > calculate_frecency(5, old[0].time, &old, NOW)
+        );
+
+        let mut previous = f64::INFINITY;
+        for age in [0, 10, 20, 60, 100, 200, 400] {
+            let score = single_link(age);
+            assert!(score < previous, "{} days ago scored {}", age, score);
+            previous = score;
+        }
+    }
+
+    #[test]
+    fn typed_visits_outweigh_links() {
+        let typed = visits(VisitKind::Typed, &[1, 2]);
+        let link = visits(VisitKind::Link, &[1, 2]);
+        let bookmark = visits(VisitKind::Bookmark, &[1, 2]);
+        let other = visits(VisitKind::Other, &[1, 2]);
+        let score = |visits: &[Visit]| calculate_frecency(2, visits[0].time, visits, NOW);
+        assert_close(score(&typed), 2.0 * score(&link));
+        assert!(score(&link) > score(&bookmark));
+        assert!(score(&bookmark) > score(&other));
+
+        // a typed visit a while ago still beats a recent link
+        let typed_old = visits(VisitKind::Typed, &[10]);
+        let link_recent = visits(VisitKind::Link, &[0]);
+        assert!(score(&typed_old) > score(&link_recent));
+    }
+
+    #[test]
+    fn visit_count_scales_the_average() {
+        let sampled = visits(VisitKind::Link, &[1, 20]);
+        let average = (100.0 + 50.0) / 2.0;
+        assert_close(
+            calculate_frecency(2, sampled[0].time, &sampled, NOW),
+            average * 2.0,
+        );
+        assert_close(
+            calculate_frecency(30, sampled[0].time, &sampled, NOW),
+            average * 30.0,
+        );
+    }
+
+    #[test]
+    fn only_the_most_recent_visits_are_sampled() {
+        let ages: Vec<i64> = (0..MAX_SAMPLED_VISITS as i64).collect();
+        let recent = visits(VisitKind::Link, &ages);
+        let mut with_old = recent.clone();
+        with_old.extend(visits(VisitKind::Link, &[200, 300]));
+        assert_close(
+            calculate_frecency(12, recent[0].time, &recent, NOW),
+            calculate_frecency(12, recent[0].time, &with_old, NOW),
+        );
+    }
+
+    #[test]
+    fn falls_back_to_the_last_visit_time() {
+        assert_close(calculate_frecency(3, days_ago(20), &[], NOW), 50.0 * 3.0);
+    }
+
+    #[test]
+    fn future_visits_count_as_just_now() {
+        let future = visits(VisitKind::Link, &[-2]);
+        assert_close(calculate_frecency(1, future[0].time, &future, NOW), 100.0);
+    }
+}
@@ -0,0 +1,265 @@
+use crate::browser_data_source::Browser;
+use crate::settings::{AppConfig, BrowserHistoryConfig};
+use globset::{Glob, GlobMatcher};
+use regex::Regex;
+use thiserror::Error;
+use url::Url;
+
+#[derive(Error, Debug)]
+pub enum DomainPatternError {
+    #[error("invalid domain glob '{0}': {1}")]
+    InvalidGlob(String, globset::Error),
+    #[error("invalid domain regex '{0}': {1}")]
+    InvalidRegex(String, regex::Error),
+    #[error("domain pattern is empty")]
+    Empty,
+}
+
+//* A domain rule from the allow or deny list */
+#[derive(Debug, Clone)]
+pub enum DomainPattern {
+    /* "example.com" matches the domain and all of its subdomains */
+    Domain(String),
+    /* "*.example.*" */
+    Glob(GlobMatcher),
+    /* "/^(www\.)?example\.com$/" */
+    Regex(Regex),
+}
+
+impl DomainPattern {
+    pub fn parse(pattern: &str) -> Result<Self, DomainPatternError> {
+        let pattern = pattern.trim();
+        if pattern.is_empty() {
+            return Err(DomainPatternError::Empty);
+        }
+
+        if pattern.len() > 1 && pattern.starts_with('/') && pattern.ends_with('/') {
+            let expression = &pattern[1..pattern.len() - 1];
+            Regex::new(expression)
+                .map(DomainPattern::Regex)
+                .map_err(|e| DomainPatternError::InvalidRegex(pattern.to_string(), e))
+        } else if pattern.contains(['*', '?', '[', '{']) {
+            Glob::new(&pattern.to_lowercase())
+                .map(|glob| DomainPattern::Glob(glob.compile_matcher()))
+                .map_err(|e| DomainPatternError::InvalidGlob(pattern.to_string(), e))
+        } else {
+            Ok(DomainPattern::Domain(pattern.to_lowercase()))
+        }
+    }
+
+    pub fn matches(&self, host: &str) -> bool {
+        match self {
+            DomainPattern::Domain(domain) => {
+                host == domain
+                    || host
+                        .strip_suffix(domain.as_str())
+                        .map_or(false, |subdomain| subdomain.ends_with('.'))
+            }
+            DomainPattern::Glob(glob) => glob.is_match(host),
+            DomainPattern::Regex(regex) => regex.is_match(host),
+        }
+    }
+}
+
+// patterns are equal when they were written the same way
+impl PartialEq for DomainPattern {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (DomainPattern::Domain(a), DomainPattern::Domain(b)) => a == b,
+            (DomainPattern::Glob(a), DomainPattern::Glob(b)) => a.glob() == b.glob(),
+            (DomainPattern::Regex(a), DomainPattern::Regex(b)) => a.as_str() == b.as_str(),
+            _ => false,
+        }
+    }
+}
+
+fn host_of(url: &str) -> Option<String> {
+    Url::parse(url)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_lowercase))
+        .filter(|host| !host.is_empty())
+}
+
+fn parse_patterns(patterns: &[String]) -> Vec<DomainPattern> {
+    patterns
+        .iter()
+        .filter_map(|pattern| {
+            DomainPattern::parse(pattern)
+                .map_err(|e| eprintln!("Ignoring browser history domain rule: {}", e))
+                .ok()
+        })
+        .collect()
+}
+
+//* The user's browser history privacy rules, compiled so they can be checked against every url */
+#[derive(Debug, Clone)]
+pub struct HistoryPrivacy {
+    disabled_browsers: Vec<Browser>,
+    allowed_domains: Vec<DomainPattern>,
+    denied_domains: Vec<DomainPattern>,
+    pub retention_days: u32,
+    pub fetch_missing_favicons: bool,
+}
+
+impl HistoryPrivacy {
+    pub fn from_config(config: &BrowserHistoryConfig) -> Self {
+        Self {
+            disabled_browsers: config.disabled_browsers.clone(),
+            allowed_domains: parse_patterns(&config.allowed_domains),
+            denied_domains: parse_patterns(&config.denied_domains),
+            retention_days: config.retention_days,
+            fetch_missing_favicons: config.fetch_missing_favicons,
+        }
+    }
+
+    //* The rules as currently configured, or the default ones when the config can't be read */
+    pub fn current() -> Self {
+        Self::from_config(&AppConfig::read().unwrap_or_default().browser_history)
+    }
+
+    pub fn is_browser_enabled(&self, browser: Browser) -> bool {
+        !self.disabled_browsers.contains(&browser)
+    }
+
+    pub fn disabled_browsers(&self) -> &[Browser] {
+        &self.disabled_browsers
+    }
+
+    // urls without a host (file://, about:blank, ...) are never indexed. When an
+    // allow list is configured only matching domains are indexed, and the deny
+    // list always wins.
+    pub fn allows_url(&self, url: &str) -> bool {
+        let host = match host_of(url) {
+            Some(host) => host,
+            None => return false,
+        };
+
+        let allowed = self.allowed_domains.is_empty()
+            || self
+                .allowed_domains
+                .iter()
+                .any(|pattern| pattern.matches(&host));
+        allowed
+            && !self
+                .denied_domains
+                .iter()
+                .any(|pattern| pattern.matches(&host))
+    }
+
+    //* Whether the deny list, which includes forgotten domains, covers `url`. Bookmarks and open tabs are only hidden by it and by disabled browsers, the allow list is about what's worth indexing from the history. */
+    pub fn denies_url(&self, url: &str) -> bool {
+        host_of(url).map_or(false, |host| {
+            self.denied_domains
+                .iter()
+                .any(|pattern| pattern.matches(&host))
+        })
+    }
+
+    //* Whether `other` keeps the same history entries, syncs have to start over when it doesn't */
+    pub fn filters_like(&self, other: &HistoryPrivacy) -> bool {
+        self.allowed_domains == other.allowed_domains
+            && self.denied_domains == other.denied_domains
+            && self.retention_days == other.retention_days
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn privacy(allowed: &[&str], denied: &[&str]) -> HistoryPrivacy {
+        HistoryPrivacy::from_config(&BrowserHistoryConfig {
+            allowed_domains: allowed.iter().map(|domain| domain.to_string()).collect(),
+            denied_domains: denied.iter().map(|domain| domain.to_string()).collect(),
+            ..BrowserHistoryConfig::default()
+        })
+    }
+
+    #[test]
+    fn domains_match_themselves_and_their_subdomains() {
+        let pattern = DomainPattern::parse(" Example.com ").unwrap();
+        assert!(matches!(pattern, DomainPattern::Domain(_)));
+        assert!(pattern.matches("example.com"));
+        assert!(pattern.matches("www.example.com"));
+        assert!(pattern.matches("a.b.example.com"));
+        assert!(!pattern.matches("notexample.com"));
+        assert!(!pattern.matches("example.com.evil.org"));
+    }
+
+    #[test]
+    fn globs_match_the_whole_host() {
+        let pattern = DomainPattern::parse("*.Example.*").unwrap();
+        assert!(matches!(pattern, DomainPattern::Glob(_)));
+        assert!(pattern.matches("www.example.com"));
+        assert!(pattern.matches("mail.example.co.uk"));
+        assert!(!pattern.matches("example.com"));
+
+        let pattern = DomainPattern::parse("ex?mple.{com,org}").unwrap();
+        assert!(pattern.matches("exemple.org"));
+        assert!(!pattern.matches("example.net"));
+    }
+
+    #[test]
+    fn regexes_are_written_between_slashes() {
+        let pattern = DomainPattern::parse(r"/^(www\.)?example\.com$/").unwrap();
+        assert!(matches!(pattern, DomainPattern::Regex(_)));
+        assert!(pattern.matches("example.com"));
+        assert!(pattern.matches("www.example.com"));
+        assert!(!pattern.matches("mail.example.com"));
+        // a lone slash is a domain, not an empty regex
+        assert!(matches!(
+            DomainPattern::parse("/").unwrap(),
+            DomainPattern::Domain(_)
+        ));
+    }
+
+    #[test]
+    fn rejects_invalid_patterns() {
+        assert!(matches!(
+            DomainPattern::parse("   "),
+            Err(DomainPatternError::Empty)
+        ));
+        assert!(matches!(
+            DomainPattern::parse("[example.com"),
+            Err(DomainPatternError::InvalidGlob(..))
+        ));
+        assert!(matches!(
+            DomainPattern::parse("/(example.com/"),
+            Err(DomainPatternError::InvalidRegex(..))
+        ));
+    }
+
+    #[test]
+    fn invalid_rules_are_ignored() {
+        let privacy = privacy(&[], &["[broken", "example.com"]);
+        assert!(!privacy.allows_url("https://example.com/"));
+        assert!(privacy.allows_url("https://rust-lang.org/"));
+    }
+
+    #[test]
+    fn the_deny_list_wins_over_the_allow_list() {
+        let privacy = privacy(&["example.com"], &["private.example.com"]);
+        assert!(privacy.allows_url("https://www.example.com/page"));
+        assert!(!privacy.allows_url("https://private.example.com/"));
+        assert!(!privacy.allows_url("https://rust-lang.org/"));
+        assert!(!privacy.allows_url("file:///etc/hosts"));
+    }
+
+    #[test]
+    fn only_denied_domains_are_hidden_from_bookmarks_and_tabs() {
+        let privacy = privacy(&["example.com"], &["private.example.com"]);
+        assert!(privacy.denies_url("https://private.example.com/"));
+        assert!(!privacy.denies_url("https://rust-lang.org/"));
+        assert!(!privacy.denies_url("javascript:alert(1)"));
+    }
+
+    #[test]
+    fn compares_the_rules_that_filter_entries() {
+        assert!(privacy(&[], &["/^a$/", "*.b"]).filters_like(&privacy(&[], &["/^a$/", "*.b"])));
+        assert!(!privacy(&[], &["a.com"]).filters_like(&privacy(&[], &["b.com"])));
+        assert!(!privacy(&["a.com"], &[]).filters_like(&privacy(&[], &[])));
+        let mut shorter = privacy(&[], &[]);
+        shorter.retention_days = 7;
+        assert!(!shorter.filters_like(&privacy(&[], &[])));
+    }
+}
@@ -0,0 +1,614 @@
+use crate::browser_data_source::{Browser, HistoryEntry};
+use crate::frecency::{Visit, VisitKind};
+use glob::glob;
+use rusqlite::Connection;
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use url::Url;
+
+//* One browser profile and the location of its history database */
+#[derive(Debug, Clone)]
+pub struct HistoryProfile {
+    pub name: String,
+    pub history_path: PathBuf,
+}
+
+//* Knows where one browser keeps its history databases and how to read them */
+pub trait HistoryReader: Send + Sync {
+    fn browser(&self) -> Browser;
+
+    //* Every profile of this browser that has a history database */
+    fn profiles(&self, home: &Path) -> Vec<HistoryProfile>;
+
+    //* Must select (url, title, visit_count, last_visit_time) with the time in unix seconds */
+    fn query(&self) -> &'static str;
+
+    //* Reads every entry visited after `since` (unix seconds) */
+    fn read(
+        &self,
+        conn: &Connection,
+        profile: &HistoryProfile,
+        since: i64,
+    ) -> rusqlite::Result<Vec<HistoryEntry>> {
+        let mut statement = conn.prepare(&format!(
+            "SELECT * FROM ({}) WHERE last_visit_time > ?1",
+            self.query()
+        ))?;
+        let entries = statement
+            .query_map([since], |row| {
+                Ok(HistoryEntry {
+                    browser: self.browser(),
+                    profile: profile.name.clone(),
+                    url: row.get(0)?,
+                    original_url: row.get(0)?,
+                    title: row.get(1)?,
+                    visit_count: row.get(2)?,
+                    last_visit_time: row.get(3)?,
+                    frecency_score: 0.0,
+                })
+            })?
+            .filter_map(Result::ok)
+            .collect();
+        Ok(entries)
+    }
+
+    //* Must select (url, visit_time, kind) with the time in unix seconds and the kind as a VisitKind number */
+    fn visits_query(&self) -> &'static str;
+
+    //* Reads every visit made after `since` (unix seconds) */
+    fn read_visits(&self, conn: &Connection, since: i64) -> rusqlite::Result<Vec<(String, Visit)>> {
+        let mut statement = conn.prepare(&format!(
+            "SELECT * FROM ({}) WHERE visit_time > ?1",
+            self.visits_query()
+        ))?;
+        let visits = statement
+            .query_map([since], |row| {
+                Ok((
+                    row.get(0)?,
+                    Visit {
+                        time: row.get(1)?,
+                        kind: VisitKind::from_i64(row.get(2)?),
+                    },
+                ))
+            })?
+            .filter_map(Result::ok)
+            .collect();
+        Ok(visits)
+    }
+
+    //* The profile's favicon database, if the browser keeps one we can read */
+    fn favicons_path(&self, _profile: &HistoryProfile) -> Option<PathBuf> {
+        None
+    }
+
+    //* Must select (page_url, image_data, width) */
+    fn favicons_query(&self) -> Option<&'static str> {
+        None
+    }
+
+    //* Reads the best favicon for each of `domains` out of the profile's favicon database */
+    fn read_favicons(
+        &self,
+        conn: &Connection,
+        domains: &HashSet<String>,
+    ) -> rusqlite::Result<HashMap<String, Vec<u8>>> {
+        let query = match self.favicons_query() {
+            Some(query) => query,
+            None => return Ok(HashMap::new()),
+        };
+        let mut statement = conn.prepare(query)?;
+        let mut rows = statement.query([])?;
+        let mut favicons: HashMap<String, (i64, Vec<u8>)> = HashMap::new();
+        while let Some(row) = rows.next()? {
+            let page_url: String = row.get(0)?;
+            let domain = match Url::parse(&page_url)
+                .ok()
+                .and_then(|url| url.domain().map(str::to_string))
+            {
+                Some(domain) if domains.contains(&domain) => domain,
+                _ => continue,
+            };
+            let image: Vec<u8> = row.get(1)?;
+            if image.is_empty() || is_svg(&image) {
+                continue;
+            }
+            let rank = favicon_rank(row.get(2)?);
+            if favicons.get(&domain).map_or(true, |(best, _)| rank > *best) {
+                favicons.insert(domain, (rank, image));
+            }
+        }
+        Ok(favicons
+            .into_iter()
+            .map(|(domain, (_, image))| (domain, image))
+            .collect())
+    }
+}
+
+// results are shown at 32px, so the largest icon up to 64px wins and bigger
+// ones are only used when nothing smaller exists
+fn favicon_rank(width: i64) -> i64 {
+    if width <= 64 {
+        width
+    } else {
+        -width
+    }
+}
+
+// the cache only holds bitmaps, Firefox also stores the SVG sources of icons
+fn is_svg(image: &[u8]) -> bool {
+    let start = String::from_utf8_lossy(&image[..image.len().min(256)]).to_lowercase();
+    let start = start.trim_start();
+    start.starts_with("<svg") || start.starts_with("<?xml")
+}
+
+pub fn history_readers() -> Vec<Box<dyn HistoryReader>> {
+    Browser::variants()
+        .iter()
+        .map(|browser| -> Box<dyn HistoryReader> {
+            match browser {
+                Browser::Firefox => Box::new(FirefoxHistoryReader),
+                Browser::Safari => Box::new(SafariHistoryReader),
+                chromium => Box::new(ChromiumHistoryReader::new(*chromium)),
+            }
+        })
+        .collect()
+}
+
+fn glob_paths(pattern: &Path) -> Vec<PathBuf> {
+    pattern
+        .to_str()
+        .and_then(|pattern| glob(pattern).ok())
+        .map(|paths| paths.filter_map(Result::ok).collect())
+        .unwrap_or_default()
+}
+
+// every page maps to an icon, which has one bitmap per size
+const CHROMIUM_FAVICONS_QUERY: &str = r#"SELECT m.page_url, b.image_data, b.width
+    FROM icon_mapping m
+    JOIN favicon_bitmaps b ON b.icon_id = m.icon_id
+    WHERE b.image_data IS NOT NULL"#;
+
+// Chromium stores times as microseconds since 1601-01-01
+const CHROMIUM_QUERY: &str = r#"SELECT url, title, visit_count,
+    CAST((last_visit_time - 11644473600000000) / 1000000 AS INTEGER) AS last_visit_time
+    FROM urls
+    WHERE hidden = 0 AND last_visit_time > 0
+    ORDER BY visit_count DESC"#;
+
+// the low byte of a transition is its core type: 0 link, 1 typed, 2 bookmark,
+// 6 top level navigation, 7 form submit, 9 omnibox keyword
+const CHROMIUM_VISITS_QUERY: &str = r#"SELECT u.url,
+    CAST((v.visit_time - 11644473600000000) / 1000000 AS INTEGER) AS visit_time,
+    CASE v.transition & 255
+        WHEN 1 THEN 1 WHEN 9 THEN 1
+        WHEN 2 THEN 2
+        WHEN 0 THEN 0 WHEN 6 THEN 0 WHEN 7 THEN 0
+        ELSE 3
+    END AS kind
+    FROM visits v
+    JOIN urls u ON u.id = v.url
+    WHERE u.hidden = 0"#;
+
+pub struct ChromiumHistoryReader {
+    browser: Browser,
+}
+
+impl ChromiumHistoryReader {
+    pub fn new(browser: Browser) -> Self {
+        Self { browser }
+    }
+
+    fn user_data_dir(&self, home: &Path) -> Option<PathBuf> {
+        let relative = match (self.browser, std::env::consts::OS) {
+            (Browser::Arc, "macos") => "Library/Application Support/Arc/User Data",
+            (Browser::Arc, "windows") => "AppData/Local/Arc/User Data",
+            (Browser::Arc, "linux") => ".config/Arc/User Data",
+            (Browser::Chrome, "macos") => "Library/Application Support/Google/Chrome",
+            (Browser::Chrome, "windows") => "AppData/Local/Google/Chrome/User Data",
+            (Browser::Chrome, "linux") => ".config/google-chrome",
+            (Browser::Brave, "macos") => "Library/Application Support/BraveSoftware/Brave-Browser",
+            (Browser::Brave, "windows") => "AppData/Local/BraveSoftware/Brave-Browser/User Data",
+            (Browser::Brave, "linux") => ".config/BraveSoftware/Brave-Browser",
+            (Browser::Edge, "macos") => "Library/Application Support/Microsoft Edge",
+            (Browser::Edge, "windows") => "AppData/Local/Microsoft/Edge/User Data",
+            (Browser::Edge, "linux") => ".config/microsoft-edge",
+            (Browser::Vivaldi, "macos") => "Library/Application Support/Vivaldi",
+            (Browser::Vivaldi, "windows") => "AppData/Local/Vivaldi/User Data",
+            (Browser::Vivaldi, "linux") => ".config/vivaldi",
+            (Browser::Chromium, "macos") => "Library/Application Support/Chromium",
+            (Browser::Chromium, "windows") => "AppData/Local/Chromium/User Data",
+            (Browser::Chromium, "linux") => ".config/chromium",
+            (Browser::Opera, "macos") => "Library/Application Support/com.operasoftware.Opera",
+            (Browser::Opera, "windows") => "AppData/Roaming/Opera Software/Opera Stable",
+            (Browser::Opera, "linux") => ".config/opera",
+            _ => return None,
+        };
+        Some(home.join(relative))
+    }
+}
+
+impl HistoryReader for ChromiumHistoryReader {
+    fn browser(&self) -> Browser {
+        self.browser
+    }
+
+    fn profiles(&self, home: &Path) -> Vec<HistoryProfile> {
+        self.user_data_dir(home)
+            .map(|dir| {
+                let profiles = chromium_profiles_from_local_state(&dir);
+                if profiles.is_empty() {
+                    chromium_profiles_from_directory(&dir)
+                } else {
+                    profiles
+                }
+            })
+            .unwrap_or_default()
+    }
+
+    fn query(&self) -> &'static str {
+        CHROMIUM_QUERY
+    }
+
+    fn visits_query(&self) -> &'static str {
+        CHROMIUM_VISITS_QUERY
+    }
+
+    fn favicons_path(&self, profile: &HistoryProfile) -> Option<PathBuf> {
+        profile
+            .history_path
+            .parent()
+            .map(|dir| dir.join("Favicons"))
+    }
+
+    fn favicons_query(&self) -> Option<&'static str> {
+        Some(CHROMIUM_FAVICONS_QUERY)
+    }
+}
+
+// `Local State` lists every profile directory along with the name the user gave it
+fn chromium_profiles_from_local_state(user_data_dir: &Path) -> Vec<HistoryProfile> {
+    let local_state = fs::read_to_string(user_data_dir.join("Local State"))
+        .ok()
+        .and_then(|contents| serde_json::from_str::<Value>(&contents).ok());
+
+    local_state
+        .as_ref()
+        .and_then(|state| state.pointer("/profile/info_cache"))
+        .and_then(Value::as_object)
+        .map(|info_cache| {
+            info_cache
+                .iter()
+                .map(|(directory, info)| HistoryProfile {
+                    name: info
+                        .get("name")
+                        .and_then(Value::as_str)
+                        .unwrap_or(directory)
+                        .to_string(),
+                    history_path: user_data_dir.join(directory).join("History"),
+                })
+                .filter(|profile| profile.history_path.is_file())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+// fallback for browsers without a usable `Local State`. Opera keeps its only
+// profile directly in the user data dir.
+fn chromium_profiles_from_directory(user_data_dir: &Path) -> Vec<HistoryProfile> {
+    let mut history_paths = vec![
+        user_data_dir.join("History"),
+        user_data_dir.join("Default/History"),
+    ];
+    history_paths.extend(glob_paths(&user_data_dir.join("Profile */History")));
+
+    history_paths
+        .into_iter()
+        .filter(|path| path.is_file())
+        .map(|history_path| HistoryProfile {
+            name: history_path
+                .parent()
+                .filter(|dir| *dir != user_data_dir)
+                .and_then(Path::file_name)
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            history_path,
+        })
+        .collect()
+}
+
+// Firefox stores times as microseconds since the unix epoch, and never-visited
+// places (bookmarks, hidden redirects) have a NULL last_visit_date
+const FIREFOX_QUERY: &str = r#"SELECT url, COALESCE(title, ''), visit_count,
+    CAST(last_visit_date / 1000000 AS INTEGER) AS last_visit_time
+    FROM moz_places
+    WHERE hidden = 0 AND last_visit_date IS NOT NULL
+    ORDER BY visit_count DESC"#;
+
+// Firefox keeps icons in their own database next to places.sqlite
+const FIREFOX_FAVICONS_QUERY: &str = r#"SELECT p.page_url, i.data, i.width
+    FROM moz_icons_to_pages ip
+    JOIN moz_pages_w_icons p ON p.id = ip.page_id
+    JOIN moz_icons i ON i.id = ip.icon_id
+    WHERE i.data IS NOT NULL"#;
+
+// visit types: 1 link, 2 typed, 3 bookmark, 8 framed link, everything else is
+// embeds, redirects, downloads and reloads
+const FIREFOX_VISITS_QUERY: &str = r#"SELECT p.url,
+    CAST(v.visit_date / 1000000 AS INTEGER) AS visit_time,
+    CASE v.visit_type
+        WHEN 2 THEN 1
+        WHEN 3 THEN 2
+        WHEN 1 THEN 0 WHEN 8 THEN 0
+        ELSE 3
+    END AS kind
+    FROM moz_historyvisits v
+    JOIN moz_places p ON p.id = v.place_id
+    WHERE p.hidden = 0"#;
+
+pub struct FirefoxHistoryReader;
+
+impl FirefoxHistoryReader {
+    fn profile_dirs(home: &Path) -> Vec<PathBuf> {
+        match std::env::consts::OS {
+            "macos" => vec![home.join("Library/Application Support/Firefox/Profiles")],
+            "windows" => vec![home.join("AppData/Roaming/Mozilla/Firefox/Profiles")],
+            "linux" => vec![
+                home.join(".mozilla/firefox"),
+                home.join("snap/firefox/common/.mozilla/firefox"),
+                home.join(".var/app/org.mozilla.firefox/.mozilla/firefox"),
+            ],
+            _ => vec![],
+        }
+    }
+}
+
+impl HistoryReader for FirefoxHistoryReader {
+    fn browser(&self) -> Browser {
+        Browser::Firefox
+    }
+
+    fn profiles(&self, home: &Path) -> Vec<HistoryProfile> {
+        Self::profile_dirs(home)
+            .iter()
+            .flat_map(|dir| glob_paths(&dir.join("*/places.sqlite")))
+            .map(|history_path| HistoryProfile {
+                // profile directories are named "<salt>.<profile name>"
+                name: history_path
+                    .parent()
+                    .and_then(Path::file_name)
+                    .map(|dir| dir.to_string_lossy().to_string())
+                    .map(|dir| match dir.split_once('.') {
+                        Some((_, name)) => name.to_string(),
+                        None => dir,
+                    })
+                    .unwrap_or_default(),
+                history_path,
+            })
+            .collect()
+    }
+
+    fn query(&self) -> &'static str {
+        FIREFOX_QUERY
+    }
+
+    fn visits_query(&self) -> &'static str {
+        FIREFOX_VISITS_QUERY
+    }
+
+    fn favicons_path(&self, profile: &HistoryProfile) -> Option<PathBuf> {
+        profile
+            .history_path
+            .parent()
+            .map(|dir| dir.join("favicons.sqlite"))
+    }
+
+    fn favicons_query(&self) -> Option<&'static str> {
+        Some(FIREFOX_FAVICONS_QUERY)
+    }
+}
+
+// Safari stores times as seconds since 2001-01-01. Titles live on the visits,
+// so only the most recent visit of each item is joined in.
+const SAFARI_QUERY: &str = r#"SELECT i.url, COALESCE(v.title, ''), i.visit_count,
+    CAST(v.visit_time + 978307200 AS INTEGER) AS last_visit_time
+    FROM history_items i
+    JOIN history_visits v ON v.id = (
+        SELECT id FROM history_visits
+        WHERE history_item = i.id
+        ORDER BY visit_time DESC
+        LIMIT 1
+    )
+    ORDER BY i.visit_count DESC"#;
+
+// Safari doesn't record how a page was reached, every visit counts as a link
+const SAFARI_VISITS_QUERY: &str = r#"SELECT i.url,
+    CAST(v.visit_time + 978307200 AS INTEGER) AS visit_time,
+    0 AS kind
+    FROM history_visits v
+    JOIN history_items i ON i.id = v.history_item"#;
+
+pub struct SafariHistoryReader;
+
+impl HistoryReader for SafariHistoryReader {
+    fn browser(&self) -> Browser {
+        Browser::Safari
+    }
+
+    fn profiles(&self, home: &Path) -> Vec<HistoryProfile> {
+        if std::env::consts::OS != "macos" {
+            return vec![];
+        }
+        let history_path = home.join("Library/Safari/History.db");
+        if history_path.is_file() {
+            vec![HistoryProfile {
+                name: String::new(),
+                history_path,
+            }]
+        } else {
+            vec![]
+        }
+    }
+
+    fn query(&self) -> &'static str {
+        SAFARI_QUERY
+    }
+
+    fn visits_query(&self) -> &'static str {
+        SAFARI_VISITS_QUERY
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rusqlite::OpenFlags;
+
+    // the fixtures are small copies of each browser's schema, see tests/fixtures
+    fn open_fixture(name: &str) -> (Connection, HistoryProfile) {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures")
+            .join(name);
+        let conn = Connection::open_with_flags(&path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+            .expect("fixture database should open");
+        let profile = HistoryProfile {
+            name: "Test".to_string(),
+            history_path: path,
+        };
+        (conn, profile)
+    }
+
+    fn summarize(entries: &[HistoryEntry]) -> Vec<(&str, &str, i64, i64)> {
+        entries
+            .iter()
+            .map(|entry| {
+                (
+                    entry.url.as_str(),
+                    entry.title.as_str(),
+                    entry.visit_count,
+                    entry.last_visit_time,
+                )
+            })
+            .collect()
+    }
+
+    fn visit(url: &str, time: i64, kind: VisitKind) -> (String, Visit) {
+        (url.to_string(), Visit { time, kind })
+    }
+
+    #[test]
+    fn chromium_reads_visible_history() {
+        let reader = ChromiumHistoryReader::new(Browser::Chrome);
+        let (conn, profile) = open_fixture("chromium_history.sqlite");
+        let entries = reader.read(&conn, &profile, 0).unwrap();
+        assert_eq!(
+            summarize(&entries),
+            vec![
+                (
+                    "https://docs.rs/rusqlite",
+                    "rusqlite - Rust",
+                    12,
+                    1700000000
+                ),
+                ("https://example.org/", "Example", 2, 1690000000),
+            ]
+        );
+        assert_eq!(entries[0].browser, Browser::Chrome);
+        assert_eq!(entries[0].profile, "Test");
+
+        let recent = reader.read(&conn, &profile, 1695000000).unwrap();
+        assert_eq!(recent.len(), 1);
+    }
+
+    #[test]
+    fn chromium_maps_transitions_to_visit_kinds() {
+        let reader = ChromiumHistoryReader::new(Browser::Chrome);
+        let (conn, _) = open_fixture("chromium_history.sqlite");
+        let mut visits = reader.read_visits(&conn, 0).unwrap();
+        visits.sort_by_key(|(_, visit)| -visit.time);
+        assert_eq!(
+            visits,
+            vec![
+                visit("https://docs.rs/rusqlite", 1700000000, VisitKind::Typed),
+                visit("https://docs.rs/rusqlite", 1699990000, VisitKind::Link),
+                visit("https://docs.rs/rusqlite", 1699980000, VisitKind::Bookmark),
+                visit("https://example.org/", 1690000000, VisitKind::Other),
+                visit("https://example.org/", 1680000000, VisitKind::Typed),
+            ]
+        );
+    }
+
+    // last_visit_date is in microseconds, a row whose time doesn't come back as
+    // an integer would be dropped instead of read
+    #[test]
+    fn firefox_converts_microsecond_timestamps() {
+        let (conn, profile) = open_fixture("firefox_places.sqlite");
+        let entries = FirefoxHistoryReader.read(&conn, &profile, 0).unwrap();
+        assert_eq!(
+            summarize(&entries),
+            vec![
+                (
+                    "https://developer.mozilla.org/",
+                    "MDN Web Docs",
+                    7,
+                    1700000000
+                ),
+                ("https://example.org/untitled", "", 1, 1690000000),
+            ]
+        );
+    }
+
+    #[test]
+    fn firefox_maps_visit_types_to_visit_kinds() {
+        let (conn, _) = open_fixture("firefox_places.sqlite");
+        let mut visits = FirefoxHistoryReader.read_visits(&conn, 0).unwrap();
+        visits.sort_by_key(|(_, visit)| -visit.time);
+        assert_eq!(
+            visits,
+            vec![
+                visit(
+                    "https://developer.mozilla.org/",
+                    1700000000,
+                    VisitKind::Typed
+                ),
+                visit(
+                    "https://developer.mozilla.org/",
+                    1699990000,
+                    VisitKind::Link
+                ),
+                visit(
+                    "https://developer.mozilla.org/",
+                    1699980000,
+                    VisitKind::Bookmark
+                ),
+                visit("https://example.org/untitled", 1690000000, VisitKind::Link),
+                visit("https://example.org/untitled", 1689000000, VisitKind::Other),
+            ]
+        );
+    }
+
+    // every visit has its own row, joining all of them would list an item once per visit
+    #[test]
+    fn safari_reads_one_entry_per_item_with_its_latest_visit() {
+        let (conn, profile) = open_fixture("safari_history.sqlite");
+        let entries = SafariHistoryReader.read(&conn, &profile, 0).unwrap();
+        assert_eq!(
+            summarize(&entries),
+            vec![
+                ("https://www.apple.com/", "Apple", 3, 1700000000),
+                ("https://example.org/", "", 1, 1690000000),
+            ]
+        );
+    }
+
+    #[test]
+    fn safari_counts_every_visit_as_a_link() {
+        let (conn, _) = open_fixture("safari_history.sqlite");
+        let visits = SafariHistoryReader.read_visits(&conn, 1695000000).unwrap();
+        assert_eq!(visits.len(), 3);
+        assert!(visits
+            .iter()
+            .all(|(url, visit)| url == "https://www.apple.com/" && visit.kind == VisitKind::Link));
+    }
+}
@@ -6,13 +6,29 @@
 extern crate objc;
 
 mod app_state;
+mod bookmarks_data_source;
 mod browser_data_source;
+mod calculator;
+mod calculator_history;
+mod commands;
+mod config_watcher;
 mod constants;
+mod exchange_rates;
 mod file_data_source;
+mod frecency;
+mod history_privacy;
+mod history_reader;
+mod open_tabs_data_source;
+mod plugin_host;
 mod query_engine;
+mod query_modes;
+mod query_parser;
+mod result_providers;
 mod settings;
+mod shortcuts;
 mod sqlite;
 mod tray;
+mod url_normalization;
 mod utilities;
 mod windows;
 
@@ -21,19 +37,27 @@ use crate::windows::{
     show_main_window, show_settings_window, toggle_main_window, toggle_settings_window,
 };
 use app_state::AppState;
+use commands::{
+    activate_open_tab, execute_plugin_action, forget_history_domain, get_settings,
+    record_calculation, save_settings, set_launch_shortcut, start_shortcut_recording,
+    stop_shortcut_recording,
+};
 use query_engine::{QueryEngine, QueryInterface};
 use serde_variant::to_variant_name;
+use shortcuts::{handle_shortcut, register_shortcuts};
 use std::env;
-use std::str::FromStr;
 use swordfish_types::SFEvent;
 use tauri::{AppHandle, Emitter, Listener, Manager};
-use tauri_plugin_global_shortcut::{Shortcut, ShortcutState};
+use tauri_plugin_global_shortcut::ShortcutState;
 use tracing::{error, info};
 
 #[tokio::main]
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub async fn run() {
     tauri::async_runtime::set(tokio::runtime::Handle::current());
+    // loading the config writes it when it's missing or outdated, which has to
+    // happen before the data sources start reading it
+    let app_state = AppState::new();
     let query_engine = QueryEngine::new();
 
     tauri::Builder::default()
@@ -52,10 +76,22 @@ pub async fn run() {
             show_settings_window,
             hide_settings_window,
             toggle_settings_window,
+            forget_history_domain,
+            get_settings,
+            save_settings,
+            set_launch_shortcut,
+            start_shortcut_recording,
+            stop_shortcut_recording,
+            execute_plugin_action,
+            record_calculation,
+            activate_open_tab,
         ])
         // .system_tray(make_tray())
         // .on_system_tray_event(handle_tray_event)
-        .manage(AppState::new())
+        .manage(app_state)
+        .manage(query_engine.plugin_host())
+        .manage(query_engine.calculator())
+        .manage(query_engine.open_tabs())
         .setup(move |app| {
             #[cfg(target_os = "macos")]
             {
@@ -78,32 +114,40 @@ pub async fn run() {
                 main_window.open_devtools();
             }
 
-            let config = app
-                .state::<AppState>()
-                .config
-                .lock()
-                .map(|config| config.read())
-                .expect("Unable to read config");
-            println!("{}", config.launch_shortcut);
             app.handle().plugin(
                 tauri_plugin_global_shortcut::Builder::new()
-                    .with_shortcuts(["ctrl+space"])?
                     .with_handler(move |app_handle, shortcut, event| {
                         if event.state == ShortcutState::Released {
-                            let launch =
-                                Shortcut::from_str(config.launch_shortcut.as_str()).unwrap();
-                            println!("shortcut: {}, launch: {}", shortcut, launch);
-                            toggle_main_window(app_handle.to_owned());
+                            handle_shortcut(app_handle, shortcut);
                         }
                     })
                     .build(),
             )?;
+            let shortcut_errors = register_shortcuts(app_handle);
+            if let Ok(mut errors) = app.state::<AppState>().config_errors.lock() {
+                for error in shortcut_errors {
+                    eprintln!("Unable to register '{}': {}", error.field, error.message);
+                    // invalid shortcuts were already reported when the config was loaded
+                    if !errors.iter().any(|existing| existing.field == error.field) {
+                        errors.push(error);
+                    }
+                }
+            }
+
+            config_watcher::watch_config(app_handle.clone());
 
             let emitter = app_handle.clone();
             let _id = app_handle.listen(to_variant_name(&SFEvent::Query).unwrap(), move |event| {
                 let str = event.payload();
                 if let Ok(query) = serde_json::from_str(str) {
-                    let res = query_engine.query(query);
+                    // read for every query so edits to the settings apply right away
+                    let config = emitter
+                        .state::<AppState>()
+                        .config
+                        .lock()
+                        .map(|config| config.clone())
+                        .unwrap_or_default();
+                    let res = query_engine.query(query, &config);
                     let _ = emitter.emit(to_variant_name(&SFEvent::QueryResult).unwrap(), res);
                 }
             });
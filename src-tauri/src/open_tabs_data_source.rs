@@ -0,0 +1,439 @@
+use crate::browser_data_source::Browser;
+use crate::history_privacy::HistoryPrivacy;
+use crate::history_reader::{history_readers, HistoryProfile};
+use fuzzy_matcher::skim::SkimMatcherV2;
+use fuzzy_matcher::FuzzyMatcher;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use std::{cmp, fs, thread};
+use swordfish_types::{DataSource, Query};
+
+// session files are rewritten by the browser every few seconds, re-reading
+// them more often than that is wasted work
+const REFRESH_INTERVAL: Duration = Duration::from_secs(5);
+
+//* A tab that is currently open in a browser window */
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct OpenTab {
+    pub browser: Browser,
+    pub profile: String,
+    pub url: String,
+    pub title: String,
+}
+
+impl OpenTab {
+    pub fn source_label(&self) -> String {
+        self.browser.source_label(&self.profile)
+    }
+}
+
+// mozlz4 files are a magic header, the decompressed size as a little endian
+// u32, and then a single raw lz4 block
+fn decompress_mozlz4(bytes: &[u8]) -> Option<Vec<u8>> {
+    const MAGIC: &[u8] = b"mozLz40\0";
+    if bytes.len() < MAGIC.len() + 4 || &bytes[..MAGIC.len()] != MAGIC {
+        return None;
+    }
+    let size_bytes: [u8; 4] = bytes[MAGIC.len()..MAGIC.len() + 4].try_into().ok()?;
+    let size = u32::from_le_bytes(size_bytes) as usize;
+    lz4_flex::block::decompress(&bytes[MAGIC.len() + 4..], size)
+        .map_err(|e| eprintln!("Error decompressing mozlz4 file: {:?}", e))
+        .ok()
+}
+
+fn read_firefox_tabs(profile: &HistoryProfile) -> Vec<OpenTab> {
+    let session_path = match profile.history_path.parent() {
+        Some(dir) => dir.join("sessionstore-backups/recovery.jsonlz4"),
+        None => return vec![],
+    };
+    let session = fs::read(&session_path)
+        .ok()
+        .and_then(|bytes| decompress_mozlz4(&bytes))
+        .and_then(|json| serde_json::from_slice::<Value>(&json).ok());
+
+    let windows = match session.as_ref().and_then(|s| s.get("windows")) {
+        Some(Value::Array(windows)) => windows,
+        _ => return vec![],
+    };
+
+    windows
+        .iter()
+        .filter_map(|window| window.get("tabs").and_then(Value::as_array))
+        .flatten()
+        .filter_map(|tab| {
+            let entries = tab.get("entries").and_then(Value::as_array)?;
+            // "index" is 1-based and points at the entry currently shown in the tab
+            let index = tab
+                .get("index")
+                .and_then(Value::as_u64)
+                .map(|index| index.saturating_sub(1) as usize)
+                .unwrap_or(entries.len().saturating_sub(1));
+            let entry = entries.get(index).or_else(|| entries.last())?;
+            Some(OpenTab {
+                browser: Browser::Firefox,
+                profile: profile.name.clone(),
+                url: entry.get("url").and_then(Value::as_str)?.to_string(),
+                title: entry
+                    .get("title")
+                    .and_then(Value::as_str)
+                    .unwrap_or("")
+                    .to_string(),
+            })
+        })
+        .collect()
+}
+
+// Chromium session commands, see components/sessions/core/session_service_commands.cc
+const SET_TAB_WINDOW: u8 = 0;
+const UPDATE_TAB_NAVIGATION: u8 = 6;
+const SET_SELECTED_NAVIGATION_INDEX: u8 = 7;
+const TAB_CLOSED: u8 = 16;
+const WINDOW_CLOSED: u8 = 17;
+
+//* Reads the primitives Chromium writes into a base::Pickle, which aligns every field to 4 bytes */
+struct PickleReader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> PickleReader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, offset: 0 }
+    }
+
+    fn read_bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        let bytes = self.bytes.get(self.offset..self.offset.checked_add(len)?)?;
+        self.offset += len.div_ceil(4) * 4;
+        Some(bytes)
+    }
+
+    fn read_i32(&mut self) -> Option<i32> {
+        self.read_bytes(4)
+            .and_then(|bytes| bytes.try_into().ok())
+            .map(i32::from_le_bytes)
+    }
+
+    fn read_string(&mut self) -> Option<String> {
+        let len = usize::try_from(self.read_i32()?).ok()?;
+        self.read_bytes(len)
+            .map(|bytes| String::from_utf8_lossy(bytes).to_string())
+    }
+
+    fn read_string16(&mut self) -> Option<String> {
+        let len = usize::try_from(self.read_i32()?).ok()?;
+        let units: Vec<u16> = self
+            .read_bytes(len.checked_mul(2)?)?
+            .chunks_exact(2)
+            .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+            .collect();
+        Some(String::from_utf16_lossy(&units))
+    }
+}
+
+#[derive(Default)]
+struct SessionTab {
+    window_id: i32,
+    selected_index: Option<i32>,
+    // navigation index -> (url, title)
+    navigations: HashMap<i32, (String, String)>,
+}
+
+// SNSS files are a "SNSS" header and version followed by a log of commands, each
+// a u16 size, a u8 command id and (size - 1) bytes of payload. Replaying the log
+// leaves us with the tabs that are still open.
+fn parse_snss(bytes: &[u8]) -> Vec<(String, String)> {
+    if bytes.len() < 8 || &bytes[..4] != b"SNSS" {
+        return vec![];
+    }
+
+    let mut tabs: HashMap<i32, SessionTab> = HashMap::new();
+    let mut closed_windows: Vec<i32> = vec![];
+    let mut offset = 8;
+    while offset + 3 <= bytes.len() {
+        let size = u16::from_le_bytes([bytes[offset], bytes[offset + 1]]) as usize;
+        let command_id = bytes[offset + 2];
+        let payload_end = offset + 2 + size;
+        if size == 0 || payload_end > bytes.len() {
+            break;
+        }
+        let payload = &bytes[offset + 3..payload_end];
+        offset = payload_end;
+
+        match command_id {
+            SET_TAB_WINDOW => {
+                let mut reader = PickleReader::new(payload);
+                if let (Some(window_id), Some(tab_id)) = (reader.read_i32(), reader.read_i32()) {
+                    tabs.entry(tab_id).or_default().window_id = window_id;
+                }
+            }
+            UPDATE_TAB_NAVIGATION => {
+                // pickled payloads start with their own u32 length header
+                let mut reader = PickleReader::new(payload);
+                let navigation = (|| {
+                    reader.read_i32()?;
+                    let tab_id = reader.read_i32()?;
+                    let index = reader.read_i32()?;
+                    let url = reader.read_string()?;
+                    let title = reader.read_string16()?;
+                    Some((tab_id, index, url, title))
+                })();
+                if let Some((tab_id, index, url, title)) = navigation {
+                    tabs.entry(tab_id)
+                        .or_default()
+                        .navigations
+                        .insert(index, (url, title));
+                }
+            }
+            SET_SELECTED_NAVIGATION_INDEX => {
+                let mut reader = PickleReader::new(payload);
+                if let (Some(tab_id), Some(index)) = (reader.read_i32(), reader.read_i32()) {
+                    tabs.entry(tab_id).or_default().selected_index = Some(index);
+                }
+            }
+            TAB_CLOSED => {
+                if let Some(tab_id) = PickleReader::new(payload).read_i32() {
+                    tabs.remove(&tab_id);
+                }
+            }
+            WINDOW_CLOSED => {
+                if let Some(window_id) = PickleReader::new(payload).read_i32() {
+                    closed_windows.push(window_id);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    tabs.into_values()
+        .filter(|tab| !closed_windows.contains(&tab.window_id))
+        .filter_map(|tab| {
+            tab.selected_index
+                .and_then(|index| tab.navigations.get(&index))
+                .or_else(|| {
+                    tab.navigations
+                        .iter()
+                        .max_by_key(|(index, _)| **index)
+                        .map(|(_, navigation)| navigation)
+                })
+                .cloned()
+        })
+        .collect()
+}
+
+// newer Chromium versions keep timestamped `Sessions/Session_*` files, older
+// ones a single `Current Session` file in the profile directory
+fn latest_chromium_session_file(profile_dir: &Path) -> Option<PathBuf> {
+    let sessions = fs::read_dir(profile_dir.join("Sessions"))
+        .map(|entries| {
+            entries
+                .filter_map(Result::ok)
+                .map(|entry| entry.path())
+                .filter(|path| {
+                    path.file_name()
+                        .map_or(false, |name| name.to_string_lossy().starts_with("Session_"))
+                })
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+
+    sessions
+        .into_iter()
+        .max_by_key(|path| fs::metadata(path).and_then(|m| m.modified()).ok())
+        .or_else(|| Some(profile_dir.join("Current Session")))
+        .filter(|path| path.is_file())
+}
+
+fn read_chromium_tabs(browser: Browser, profile: &HistoryProfile) -> Vec<OpenTab> {
+    profile
+        .history_path
+        .parent()
+        .and_then(latest_chromium_session_file)
+        .and_then(|path| fs::read(path).ok())
+        .map(|bytes| parse_snss(&bytes))
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|(url, _)| !url.starts_with("chrome://") && !url.is_empty())
+        .map(|(url, title)| OpenTab {
+            browser,
+            profile: profile.name.clone(),
+            url,
+            title,
+        })
+        .collect()
+}
+
+// disabled browsers and denied domains apply to open tabs like they do to the history
+fn read_open_tabs(home: &Path, privacy: &HistoryPrivacy) -> Vec<OpenTab> {
+    history_readers()
+        .par_iter()
+        .filter(|reader| privacy.is_browser_enabled(reader.browser()))
+        .flat_map(|reader| {
+            let browser = reader.browser();
+            reader
+                .profiles(home)
+                .iter()
+                .flat_map(|profile| match browser {
+                    // Safari does not keep its open tabs anywhere we can read them
+                    Browser::Safari => vec![],
+                    Browser::Firefox => read_firefox_tabs(profile),
+                    chromium => read_chromium_tabs(chromium, profile),
+                })
+                .filter(|tab| !privacy.denies_url(&tab.url))
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+// the application names AppleScript knows the browsers by. Only browsers with
+// Chrome's scripting dictionary are listed, the others can't switch tabs by script.
+fn scriptable_app_name(browser: Browser) -> Option<&'static str> {
+    match browser {
+        Browser::Chrome => Some("Google Chrome"),
+        Browser::Brave => Some("Brave Browser"),
+        Browser::Edge => Some("Microsoft Edge"),
+        Browser::Vivaldi => Some("Vivaldi"),
+        Browser::Chromium => Some("Chromium"),
+        _ => None,
+    }
+}
+
+fn applescript_string(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+// selects the first tab showing `url` and brings its window to the front, the
+// script answers "true" when it found one
+fn activate_tab_script(app_name: &str, url: &str) -> String {
+    format!(
+        r#"if application {app} is not running then return false
+tell application {app}
+    repeat with w in windows
+        set i to 1
+        repeat with t in tabs of w
+            if URL of t is {url} then
+                set active tab index of w to i
+                set index of w to 1
+                activate
+                return true
+            end if
+            set i to i + 1
+        end repeat
+    end repeat
+end tell
+return false"#,
+        app = applescript_string(app_name),
+        url = applescript_string(url),
+    )
+}
+
+//* The open tabs as of the last refresh, shared between the search and the thread refreshing them */
+#[derive(Clone)]
+pub struct OpenTabsDataSource {
+    name: String,
+    tabs: Arc<RwLock<Vec<OpenTab>>>,
+}
+
+impl OpenTabsDataSource {
+    pub fn read(&self) -> Option<Vec<OpenTab>> {
+        self.tabs.read().ok().map(|tabs| tabs.clone())
+    }
+
+    //* Re-reads the session files on its own thread, so queries never wait on them */
+    pub fn start_background_refresh(&self) {
+        let source = self.clone();
+        thread::spawn(move || loop {
+            thread::sleep(REFRESH_INTERVAL);
+            if let Some(home) = dirs::home_dir() {
+                let tabs = read_open_tabs(&home, &HistoryPrivacy::current());
+                if let Ok(mut cached) = source.tabs.write() {
+                    *cached = tabs;
+                }
+            }
+        });
+    }
+
+    //* Switches to the open tab showing `url`. Only works on macOS for browsers that can be scripted, returns false when the tab should be opened again instead. */
+    pub fn activate(&self, url: &str) -> bool {
+        if std::env::consts::OS != "macos" {
+            return false;
+        }
+        let mut app_names: Vec<&str> = self
+            .read()
+            .unwrap_or_default()
+            .iter()
+            .filter(|tab| tab.url == url)
+            .filter_map(|tab| scriptable_app_name(tab.browser))
+            .collect();
+        app_names.sort_unstable();
+        app_names.dedup();
+        app_names.into_iter().any(|app_name| {
+            match Command::new("osascript")
+                .arg("-e")
+                .arg(activate_tab_script(app_name, url))
+                .output()
+            {
+                Ok(output) => String::from_utf8_lossy(&output.stdout).trim() == "true",
+                Err(e) => {
+                    eprintln!("Error switching to the tab in {}: {}", app_name, e);
+                    false
+                }
+            }
+        })
+    }
+}
+
+impl DataSource<Vec<OpenTab>> for OpenTabsDataSource {
+    fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            tabs: Arc::new(RwLock::new(vec![])),
+        }
+    }
+
+    fn update_cache(&mut self) {
+        let home = match dirs::home_dir() {
+            Some(home) => home,
+            None => {
+                eprintln!(
+                    "Unable to locate the home directory, skipping {}",
+                    self.name
+                );
+                return;
+            }
+        };
+        let tabs = read_open_tabs(&home, &HistoryPrivacy::current());
+        println!("Found {} open browser tabs", tabs.len());
+        if let Ok(mut cached) = self.tabs.write() {
+            *cached = tabs;
+        }
+    }
+
+    fn query(&self, query: &Query) -> Option<Vec<OpenTab>> {
+        self.read().map(|tabs| {
+            let matcher = SkimMatcherV2::default().ignore_case();
+            let mut scored_tabs: Vec<(i64, OpenTab)> = tabs
+                .into_iter()
+                .filter_map(|tab| {
+                    let score = cmp::max(
+                        matcher
+                            .fuzzy_match(&tab.title, &query.search_string)
+                            .unwrap_or(0),
+                        matcher
+                            .fuzzy_match(&tab.url, &query.search_string)
+                            .unwrap_or(0),
+                    );
+                    (score > 0).then_some((score, tab))
+                })
+                .collect();
+
+            scored_tabs.sort_by(|a, b| b.0.cmp(&a.0));
+            scored_tabs.into_iter().map(|(_, tab)| tab).collect()
+        })
+    }
+}
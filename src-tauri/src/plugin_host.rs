@@ -0,0 +1,471 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use swordfish_types::{Query, QueryFilter, QueryMode, ResultDetails, ResultItem, ResultType};
+use swordfish_utilities::get_plugins_path;
+use thiserror::Error;
+
+use crate::result_providers::{ProviderResult, ResultProvider};
+
+// Plugins are executables in the plugins directory next to config.json. Each one is
+// started once and talks JSON-RPC 2.0 over stdio, one message per line:
+//
+//   initialize      {"version": 1}                  -> {"name": "Jira", "modes": ["Search"]}
+//   query           {"query": Query, "filters": []} -> [{"heading", "subheading", "value", "iconPath", "description"}]
+//   execute_action  {"value": "..."}                -> anything, the answer is ignored
+//   cancel          {"id": 12}                      (a notification, it has no id and gets no answer)
+//
+// `cancel` is sent once the host stopped waiting for a request, e.g. a query that took
+// longer than the provider's timeout, plugins that answer slowly can use it to drop the
+// work, the others can ignore it. Lines on stdout that aren't a response are printed as
+// the plugin's log. Plugins should exit once stdin is closed, a plugin that exits on its
+// own isn't restarted until swordfish is.
+
+//* Bumped whenever the protocol changes in a way that breaks existing plugins, it's sent with `initialize` */
+pub const PROTOCOL_VERSION: u32 = 1;
+//* Plugins are providers whose id is this followed by the name of their executable, e.g. "plugin:jira" */
+pub const PLUGIN_PROVIDER_PREFIX: &str = "plugin:";
+const INITIALIZE_TIMEOUT: Duration = Duration::from_secs(5);
+// queries from the registry wait for the provider's timeout instead, this only keeps a
+// plugin that never answers from holding on to a thread
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+// messages waiting for a plugin to read them, a plugin this far behind isn't reading anymore
+const WRITE_QUEUE_CAPACITY: usize = 64;
+
+#[derive(Error, Debug)]
+pub enum PluginError {
+    #[error("unable to talk to the plugin: {0}")]
+    Io(#[from] io::Error),
+    #[error("the plugin has exited")]
+    Exited,
+    #[error("the plugin stopped reading its input")]
+    NotReading,
+    #[error("the plugin didn't answer within {}ms", .0.as_millis())]
+    Timeout(Duration),
+    #[error("the plugin answered with error {code}: {message}")]
+    Rpc { code: i64, message: String },
+    #[error("the plugin's answer is not valid: {0}")]
+    InvalidResponse(#[from] serde_json::Error),
+    #[error("there is no plugin called '{0}'")]
+    NotFound(String),
+}
+
+#[derive(Serialize)]
+struct Request<'a> {
+    jsonrpc: &'static str,
+    id: u64,
+    method: &'a str,
+    params: Value,
+}
+
+#[derive(Serialize)]
+struct Notification<'a> {
+    jsonrpc: &'static str,
+    method: &'a str,
+    params: Value,
+}
+
+#[derive(Deserialize)]
+struct Response {
+    id: u64,
+    result: Option<Value>,
+    error: Option<ResponseError>,
+}
+
+#[derive(Deserialize)]
+struct ResponseError {
+    code: i64,
+    message: String,
+}
+
+#[derive(Deserialize)]
+struct InitializeResult {
+    name: String,
+    #[serde(default = "default_modes")]
+    modes: Vec<QueryMode>,
+}
+
+fn default_modes() -> Vec<QueryMode> {
+    vec![QueryMode::Search]
+}
+
+//* What a plugin answers `query` with, the details of the result items are filled in by the host */
+#[derive(Deserialize)]
+struct PluginResult {
+    heading: String,
+    #[serde(default)]
+    subheading: String,
+    value: String,
+    #[serde(rename = "iconPath", default)]
+    icon_path: Option<String>,
+    #[serde(default)]
+    description: Option<String>,
+}
+
+type PendingRequests = Arc<Mutex<HashMap<u64, mpsc::Sender<Result<Value, PluginError>>>>>;
+
+//* A running plugin executable */
+pub struct Plugin {
+    id: String,
+    name: String,
+    modes: Vec<QueryMode>,
+    child: Mutex<Child>,
+    /* lines for the thread writing to the plugin's stdin, which a plugin that stops reading blocks */
+    writer: mpsc::SyncSender<String>,
+    pending: PendingRequests,
+    next_request_id: AtomicU64,
+}
+
+impl Plugin {
+    //* Starts the executable and waits for it to answer `initialize` */
+    pub fn start(path: &Path) -> Result<Self, PluginError> {
+        let file_stem = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let mut child = Command::new(path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .spawn()?;
+        let (stdin, stdout) = match (child.stdin.take(), child.stdout.take()) {
+            (Some(stdin), Some(stdout)) => (stdin, stdout),
+            _ => {
+                child.kill().ok();
+                return Err(PluginError::Exited);
+            }
+        };
+
+        let (writer, lines) = mpsc::sync_channel(WRITE_QUEUE_CAPACITY);
+        thread::spawn(move || write_lines(stdin, lines));
+
+        let pending: PendingRequests = Arc::new(Mutex::new(HashMap::new()));
+        let responses = Arc::clone(&pending);
+        let log_name = file_stem.clone();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let line = match line {
+                    Ok(line) => line,
+                    Err(_) => break,
+                };
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let response = match serde_json::from_str::<Response>(&line) {
+                    Ok(response) => response,
+                    Err(_) => {
+                        println!("[{}] {}", log_name, line);
+                        continue;
+                    }
+                };
+                let sender = responses
+                    .lock()
+                    .ok()
+                    .and_then(|mut pending| pending.remove(&response.id));
+                // nobody is waiting anymore when the request timed out
+                if let Some(sender) = sender {
+                    let result = match response.error {
+                        Some(error) => Err(PluginError::Rpc {
+                            code: error.code,
+                            message: error.message,
+                        }),
+                        None => Ok(response.result.unwrap_or(Value::Null)),
+                    };
+                    sender.send(result).ok();
+                }
+            }
+            // the plugin is gone, dropping the senders fails the requests still waiting
+            eprintln!(
+                "Plugin '{}' has exited, its results are missing until swordfish is restarted",
+                log_name
+            );
+            if let Ok(mut pending) = responses.lock() {
+                pending.clear();
+            }
+        });
+
+        let mut plugin = Self {
+            id: format!("{}{}", PLUGIN_PROVIDER_PREFIX, file_stem),
+            name: file_stem,
+            modes: vec![],
+            child: Mutex::new(child),
+            writer,
+            pending,
+            next_request_id: AtomicU64::new(1),
+        };
+        let initialized: InitializeResult = serde_json::from_value(plugin.request(
+            "initialize",
+            json!({ "version": PROTOCOL_VERSION }),
+            INITIALIZE_TIMEOUT,
+        )?)?;
+        plugin.name = initialized.name;
+        plugin.modes = initialized.modes;
+        Ok(plugin)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    // only queues the message, so a plugin that doesn't read can't block the caller
+    fn write_line(&self, message: &str) -> Result<(), PluginError> {
+        self.writer
+            .try_send(message.to_string())
+            .map_err(|e| match e {
+                mpsc::TrySendError::Full(_) => PluginError::NotReading,
+                mpsc::TrySendError::Disconnected(_) => PluginError::Exited,
+            })
+    }
+
+    fn request(
+        &self,
+        method: &str,
+        params: Value,
+        timeout: Duration,
+    ) -> Result<Value, PluginError> {
+        let id = self.next_request_id.fetch_add(1, Ordering::Relaxed);
+        let message = serde_json::to_string(&Request {
+            jsonrpc: "2.0",
+            id,
+            method,
+            params,
+        })?;
+        let (sender, receiver) = mpsc::channel();
+        self.pending
+            .lock()
+            .map_err(|_| PluginError::Exited)?
+            .insert(id, sender);
+
+        let result = self
+            .write_line(&message)
+            .and_then(|_| match receiver.recv_timeout(timeout) {
+                Ok(result) => result,
+                Err(mpsc::RecvTimeoutError::Timeout) => Err(PluginError::Timeout(timeout)),
+                Err(mpsc::RecvTimeoutError::Disconnected) => Err(PluginError::Exited),
+            });
+        if result.is_err() {
+            if let Ok(mut pending) = self.pending.lock() {
+                pending.remove(&id);
+            }
+        }
+        if let Err(PluginError::Timeout(_)) = result {
+            self.notify("cancel", json!({ "id": id }));
+        }
+        result
+    }
+
+    // failing to notify the plugin is noticed by the next request
+    fn notify(&self, method: &str, params: Value) {
+        let message = serde_json::to_string(&Notification {
+            jsonrpc: "2.0",
+            method,
+            params,
+        });
+        if let Ok(message) = message {
+            self.write_line(&message).ok();
+        }
+    }
+
+    //* Gives up once `timeout` has passed, the plugin is told to cancel the query then */
+    pub fn query_results(
+        &self,
+        query: &Query,
+        filters: &[QueryFilter],
+        timeout: Duration,
+    ) -> Result<Vec<ResultItem>, PluginError> {
+        let results: Vec<PluginResult> = serde_json::from_value(self.request(
+            "query",
+            json!({ "query": query, "filters": filters }),
+            timeout,
+        )?)?;
+        Ok(results
+            .into_iter()
+            .map(|result| ResultItem {
+                heading: result.heading,
+                subheading: result.subheading,
+                value: result.value,
+                icon_path: result.icon_path,
+                r#type: ResultType::Plugin,
+                details: Some(ResultDetails::Plugin {
+                    plugin: self.id.clone(),
+                    description: result.description,
+                }),
+            })
+            .collect())
+    }
+
+    //* Lets the plugin do whatever picking one of its results means, e.g. opening a ticket */
+    pub fn execute_action(&self, value: &str) -> Result<(), PluginError> {
+        self.request("execute_action", json!({ "value": value }), REQUEST_TIMEOUT)
+            .map(|_| ())
+    }
+}
+
+// ends when the plugin closes its stdin or the plugin is dropped, which closes it in turn
+fn write_lines(mut stdin: ChildStdin, lines: mpsc::Receiver<String>) {
+    for line in lines {
+        if let Err(e) = writeln!(stdin, "{}", line).and_then(|_| stdin.flush()) {
+            eprintln!("Unable to write to a plugin: {}", e);
+            break;
+        }
+    }
+}
+
+impl Drop for Plugin {
+    fn drop(&mut self) {
+        if let Ok(mut child) = self.child.lock() {
+            child.kill().ok();
+            child.wait().ok();
+        }
+    }
+}
+
+impl ResultProvider for Plugin {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn section(&self) -> &str {
+        &self.name
+    }
+
+    fn modes(&self) -> &[QueryMode] {
+        &self.modes
+    }
+
+    fn query(&self, query: &Query, filters: &[QueryFilter]) -> Vec<ProviderResult> {
+        self.query_within(query, filters, REQUEST_TIMEOUT)
+    }
+
+    // the registry stops waiting after the provider's timeout, so should the plugin
+    fn query_within(
+        &self,
+        query: &Query,
+        filters: &[QueryFilter],
+        timeout: Duration,
+    ) -> Vec<ProviderResult> {
+        match self.query_results(query, filters, timeout) {
+            Ok(results) => results
+                .into_iter()
+                .enumerate()
+                .map(|(rank, item)| ProviderResult::ranked(item, rank))
+                .collect(),
+            Err(e) => {
+                eprintln!("Plugin '{}' failed to answer a query: {}", self.name, e);
+                vec![]
+            }
+        }
+    }
+}
+
+//* The plugins that started successfully, cloning it shares the running plugins */
+#[derive(Clone, Default)]
+pub struct PluginHost {
+    plugins: Vec<Arc<Plugin>>,
+}
+
+impl PluginHost {
+    pub fn start() -> Self {
+        match get_plugins_path() {
+            Some(dir) => Self::start_in(&dir),
+            None => Self::default(),
+        }
+    }
+
+    //* Starts every executable in `dir` at the same time, the ones that fail to start or initialize are skipped */
+    pub fn start_in(dir: &Path) -> Self {
+        let paths = plugin_executables(dir);
+        let started: Vec<Result<Plugin, PluginError>> = thread::scope(|scope| {
+            paths
+                .iter()
+                .map(|path| scope.spawn(move || Plugin::start(path)))
+                .collect::<Vec<_>>()
+                .into_iter()
+                .map(|handle| handle.join().unwrap_or(Err(PluginError::Exited)))
+                .collect()
+        });
+
+        let mut plugins: Vec<Arc<Plugin>> = vec![];
+        for (path, plugin) in paths.iter().zip(started) {
+            match plugin {
+                Ok(plugin) => {
+                    println!("Loaded plugin '{}' from {}", plugin.name, path.display());
+                    plugins.push(Arc::new(plugin));
+                }
+                Err(e) => eprintln!("Unable to load the plugin {}: {}", path.display(), e),
+            }
+        }
+        Self { plugins }
+    }
+
+    pub fn plugins(&self) -> &[Arc<Plugin>] {
+        &self.plugins
+    }
+
+    pub fn execute_action(&self, plugin: &str, value: &str) -> Result<(), PluginError> {
+        self.plugins
+            .iter()
+            .find(|candidate| candidate.id == plugin)
+            .ok_or_else(|| PluginError::NotFound(plugin.to_string()))?
+            .execute_action(value)
+    }
+}
+
+// sorted so plugins are registered in the same order on every start, of executables
+// with the same name only the first one is used
+fn plugin_executables(dir: &Path) -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = match fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(Result::ok)
+            .map(|entry| entry.path())
+            .filter(|path| is_executable(path))
+            .filter(|path| {
+                !path
+                    .file_name()
+                    .map(|name| name.to_string_lossy().starts_with('.'))
+                    .unwrap_or(true)
+            })
+            .collect(),
+        Err(e) => {
+            eprintln!("Unable to read the plugins directory: {}", e);
+            vec![]
+        }
+    };
+    paths.sort();
+    let mut file_stems = vec![];
+    paths.retain(|path| {
+        let file_stem = path.file_stem().map(|stem| stem.to_os_string());
+        if file_stems.contains(&file_stem) {
+            eprintln!(
+                "Skipping the plugin {}, one with the same name was loaded already",
+                path.display()
+            );
+            return false;
+        }
+        file_stems.push(file_stem);
+        true
+    });
+    paths
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    fs::metadata(path)
+        .map(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
@@ -1,33 +1,49 @@
 use crate::windows::hide_main_window;
 use crate::{
+    bookmarks_data_source::{Bookmark, BookmarksDataSource},
     browser_data_source::{BrowserHistoryDataSource, HistoryEntry},
+    calculator::{Calculator, CalculatorHistoryProvider, CalculatorProvider},
+    exchange_rates::ExchangeRateSource,
     file_data_source::FileDataSource,
+    open_tabs_data_source::{OpenTab, OpenTabsDataSource},
+    plugin_host::PluginHost,
+    query_modes::apply_mode_prefix,
+    query_parser::parse_query,
+    result_providers::{ProviderRegistry, ProviderResult, ResultProvider},
+    settings::AppConfig,
+    url_normalization::normalize_url,
     windows::acquire_main_window,
 };
 use axum::error_handling::HandleErrorLayer;
 use axum::BoxError;
 use axum::{extract::State, response::IntoResponse, routing::post, Json, Router};
-use fend_core::{FendResult, SpanKind};
+use chrono::{DateTime, Local};
+use fuzzy_matcher::skim::SkimMatcherV2;
+use fuzzy_matcher::FuzzyMatcher;
 use reqwest::StatusCode;
 use serde_variant::to_variant_name;
 use std::fs::{self};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 use swordfish_types::{
-    DataSource, Query, QueryMode, QueryResult, ReceivedEvent, ResultDetails, ResultItem,
-    ResultType, SFEvent,
+    DataSource, Query, QueryFilter, QueryMode, QueryResult, ReceivedEvent, ResultDetails,
+    ResultItem, ResultType, SFEvent,
 };
-use swordfish_utilities::get_favicon_path;
+use swordfish_utilities::{get_favicon_path, get_scripts_path};
 use tauri::{AppHandle, Emitter, Listener, Manager};
 use tower::ServiceBuilder;
 
 pub trait QueryInterface {
     fn new() -> Self;
-    fn query(&self, query: Query) -> QueryResult;
+    fn query(&self, query: Query, config: &AppConfig) -> QueryResult;
 }
 
 pub struct QueryEngine {
-    browser_history: BrowserHistoryDataSource,
-    file_data: FileDataSource,
+    providers: ProviderRegistry,
+    plugins: PluginHost,
+    calculator: Calculator,
+    open_tabs: OpenTabsDataSource,
 }
 
 fn is_empty_query(query: &Query) -> bool {
@@ -35,6 +51,21 @@ fn is_empty_query(query: &Query) -> bool {
 }
 
 impl QueryEngine {
+    //* Shares the running plugins, e.g. with the commands that run their actions */
+    pub fn plugin_host(&self) -> PluginHost {
+        self.plugins.clone()
+    }
+
+    //* Shares the open tabs, e.g. with the command switching to one of them */
+    pub fn open_tabs(&self) -> OpenTabsDataSource {
+        self.open_tabs.clone()
+    }
+
+    //* Shares the calculator session, e.g. with the command adding picked results to its history */
+    pub fn calculator(&self) -> Calculator {
+        self.calculator.clone()
+    }
+
     pub fn start_ipc_server(handle: &AppHandle) {
         let handle = handle.clone();
         tokio::spawn(async move {
@@ -94,154 +125,397 @@ impl QueryEngine {
 
 impl QueryInterface for QueryEngine {
     fn new() -> Self {
-        let mut browser_history = BrowserHistoryDataSource::new("history");
+        // the first connection creates and migrates the tables, the sync thread
+        // only gets its own once that's done
+        let browser_history = BrowserHistoryDataSource::new("history");
+        BrowserHistoryDataSource::new("history").start_background_sync();
+        let bookmarks = BookmarksDataSource::new("bookmarks");
+        BookmarksDataSource::new("bookmarks").start_background_refresh();
+        let mut open_tabs = OpenTabsDataSource::new("open_tabs");
         let mut file_data = FileDataSource::new("sf_cache");
-        browser_history.update_cache();
+        open_tabs.update_cache();
+        open_tabs.start_background_refresh();
         file_data.update_cache();
 
+        // chat happens in the frontend, so no provider answers QueryMode::Chat
+        let file_data = Arc::new(Mutex::new(file_data));
+        let mut providers = ProviderRegistry::new();
+        providers.register(Arc::new(AppsProvider {
+            file_data: Arc::clone(&file_data),
+        }));
+        providers.register(Arc::new(FilesProvider { file_data }));
+        providers.register(Arc::new(OpenTabsProvider {
+            open_tabs: open_tabs.clone(),
+        }));
+        providers.register(Arc::new(BookmarksProvider {
+            bookmarks: Mutex::new(bookmarks),
+        }));
+        providers.register(Arc::new(BrowserHistoryProvider {
+            browser_history: Mutex::new(browser_history),
+        }));
+        providers.register(Arc::new(ScriptsProvider));
+        let exchange_rates = ExchangeRateSource::new();
+        exchange_rates.start_background_refresh();
+        let calculator = Calculator::new(exchange_rates);
+        calculator.start_settling();
+        providers.register(Arc::new(CalculatorProvider::new(calculator.clone())));
+        providers.register(Arc::new(CalculatorHistoryProvider::new(calculator.clone())));
+        let plugins = PluginHost::start();
+        for plugin in plugins.plugins() {
+            providers.register(plugin.clone());
+        }
+
         Self {
-            browser_history,
-            file_data,
+            providers,
+            plugins,
+            calculator,
+            open_tabs,
         }
     }
 
-    fn query(&self, query: Query) -> QueryResult {
-        let mut results: Vec<ResultItem> = vec![];
-        if let Some(calculator_result) = get_calculator_result(&query) {
-            if calculator_result.heading != query.search_string {
-                results.push(calculator_result);
-            }
+    fn query(&self, query: Query, config: &AppConfig) -> QueryResult {
+        let query = apply_mode_prefix(query, &config.mode_prefixes);
+        // only files can be filtered, the filters are sent back so they can be
+        // shown apart from the search term
+        let (query, filters) = if query.mode == QueryMode::Search {
+            let parsed = parse_query(&query.search_string);
+            (
+                Query {
+                    search_string: parsed.term,
+                    ..query
+                },
+                parsed.filters,
+            )
+        } else {
+            (query, vec![])
+        };
+
+        let merged = self.providers.query(&query, &filters, &config.providers);
+        QueryResult {
+            results: merged.results,
+            filters,
+            mode: query.mode,
+            sections: merged.sections,
         }
+    }
+}
 
-        match query.mode {
-            QueryMode::Search => {
-                if is_empty_query(&query) {
-                    return QueryResult { results: vec![] };
-                }
+// both look through the same file index, apps only read the applications' rows of it
+struct AppsProvider {
+    file_data: Arc<Mutex<FileDataSource>>,
+}
 
-                if let Some(dirs) = self.file_data.query(&query) {
-                    QueryResult {
-                        results: dirs
-                            .iter()
-                            .map(|info| ResultItem::from(info.to_owned()))
-                            .collect(),
-                    }
-                } else {
-                    QueryResult { results: vec![] }
-                }
-            }
-            QueryMode::BrowserHistory => {
-                if is_empty_query(&query) {
-                    return QueryResult { results: vec![] };
-                }
-                self.browser_history
-                    .query(&query)
-                    .map(|entries| QueryResult {
-                        results: entries
-                            .iter()
-                            .map(|item| ResultItem {
-                                heading: item.title.clone(),
-                                subheading: item.url.clone(),
-                                value: item.url.clone(),
-                                details: Some(ResultDetails::BrowserHistory {
-                                    url: item.url.clone(),
-                                    image_url: "".to_string(),
-                                    heading: item.title.clone(),
-                                    subheading: item.url.clone(),
-                                }),
-                                icon_path: get_favicon_path(item.url.as_str()),
-                                r#type: ResultType::BrowserHistory,
-                            })
-                            .collect(),
-                    })
-                    .unwrap_or(QueryResult { results: vec![] })
-            }
-            QueryMode::Chat => QueryResult { results: vec![] },
-            QueryMode::Scripts => {
-                let file_content =
-                    match fs::read_to_string("/Users/atlas/Desktop/swordfish-test-script.ts") {
-                        Ok(content) => content,
-                        Err(_) => "".to_string(),
-                    };
-
-                QueryResult {
-                    results: vec![ResultItem {
-                        heading: "Scripts".to_string(),
-                        subheading: "Run scripts".to_string(),
-                        value: "Scripts".to_string(),
-                        icon_path: None,
-                        r#type: ResultType::Script,
-                        details: Some(ResultDetails::Script {
-                            path: "/Desktop".to_string(),
-                            last_modified: "2024-08-24".to_string(),
-                            language: "ts".to_string(),
-                            content: "".to_string(),
-                            parsed_content: Some(file_content),
-                        }),
-                    }],
-                }
-            }
+impl ResultProvider for AppsProvider {
+    fn id(&self) -> &str {
+        "apps"
+    }
+
+    fn section(&self) -> &str {
+        "Applications"
+    }
+
+    fn modes(&self) -> &[QueryMode] {
+        &[QueryMode::Search]
+    }
+
+    fn query(&self, query: &Query, filters: &[QueryFilter]) -> Vec<ProviderResult> {
+        if is_empty_query(query) {
+            return vec![];
         }
+        let apps = match self.file_data.lock() {
+            Ok(file_data) => file_data
+                .query_applications(&query.search_string, filters)
+                .unwrap_or_default(),
+            Err(_) => return vec![],
+        };
+        apps.into_iter()
+            .enumerate()
+            .map(|(rank, app)| ProviderResult::ranked(ResultItem::from(app), rank))
+            .collect()
     }
 }
 
-fn get_calculator_result(query: &Query) -> Option<ResultItem> {
-    let mut context = fend_core::Context::new();
-    if let Ok(fend_result) = fend_core::evaluate(&query.search_string, &mut context) {
-        if fend_result.get_main_result().is_empty() {
-            return None;
+struct FilesProvider {
+    file_data: Arc<Mutex<FileDataSource>>,
+}
+
+impl ResultProvider for FilesProvider {
+    fn id(&self) -> &str {
+        "files"
+    }
+
+    fn section(&self) -> &str {
+        "Files"
+    }
+
+    fn modes(&self) -> &[QueryMode] {
+        &[QueryMode::Search]
+    }
+
+    fn query(&self, query: &Query, filters: &[QueryFilter]) -> Vec<ProviderResult> {
+        if is_empty_query(query) && filters.is_empty() {
+            return vec![];
         }
+        let files = match self.file_data.lock() {
+            Ok(file_data) => file_data
+                .query_files(&query.search_string, filters)
+                .unwrap_or_default(),
+            Err(_) => return vec![],
+        };
+        files
+            .into_iter()
+            .enumerate()
+            .map(|(rank, file)| ProviderResult::ranked(ResultItem::from(file), rank))
+            .collect()
+    }
+}
 
-        let parsed_result = format_calculator_result(&fend_result);
+// urls are compared in normalized form, so the same page is only listed once
+// across tabs, bookmarks and history, which share a section
+struct OpenTabsProvider {
+    open_tabs: OpenTabsDataSource,
+}
 
-        return Some(ResultItem {
-            heading: fend_result.get_main_result().to_string(),
-            subheading: parsed_result,
-            value: fend_result.get_main_result().to_string(),
-            icon_path: None,
-            r#type: ResultType::Calculator,
-            details: None,
-        });
+impl ResultProvider for OpenTabsProvider {
+    fn id(&self) -> &str {
+        "open_tabs"
+    }
+
+    fn section(&self) -> &str {
+        "Web"
+    }
+
+    fn modes(&self) -> &[QueryMode] {
+        &[QueryMode::BrowserHistory, QueryMode::Search]
+    }
+
+    fn query(&self, query: &Query, filters: &[QueryFilter]) -> Vec<ProviderResult> {
+        if is_empty_query(query) || !filters.is_empty() {
+            return vec![];
+        }
+        self.open_tabs
+            .query(query)
+            .unwrap_or_default()
+            .iter()
+            .enumerate()
+            .map(|(rank, item)| ProviderResult {
+                key: normalize_url(&item.url),
+                ..ProviderResult::ranked(open_tab_result(item), rank)
+            })
+            .collect()
     }
-    None
 }
 
-fn format_calculator_result(fend_result: &FendResult) -> String {
-    let calculator_content = fend_result
-        .get_main_result_spans()
-        .filter(|span| !span.string().is_empty())
-        .map(|span| match span.kind() {
-            SpanKind::Boolean => format!(
-                "<span class=\"calculator-boolean\">{}</span>",
-                span.string()
-            ),
-            SpanKind::Number => {
-                format!("<span class=\"calculator-number\">{}</span>", span.string())
-            }
-            SpanKind::BuiltInFunction => format!(
-                "<span class=\"calculator-builtin-fn\">{}</span>",
-                span.string()
-            ),
-            SpanKind::Keyword => format!(
-                "<span class=\"calculator-keyword\">{}</span>",
-                span.string()
-            ),
-            SpanKind::String => {
-                format!("<span class=\"calculator-string\">{}</span>", span.string())
-            }
-            SpanKind::Date => format!("<span class=\"calculator-date\">{}</span>", span.string()),
-            SpanKind::Whitespace => format!(
-                "<span class=\"calculator-whitespace\">{}</span>",
-                span.string()
-            ),
-            SpanKind::Ident => format!("<span class=\"calculator-ident\">{}</span>", span.string()),
-            SpanKind::Other => format!("<span class=\"calculator-other\">{}</span>", span.string()),
-            _ => format!(
-                "<span class=\"calculator-unknown\">{}</span>",
-                span.string()
-            ),
+struct BookmarksProvider {
+    bookmarks: Mutex<BookmarksDataSource>,
+}
+
+impl ResultProvider for BookmarksProvider {
+    fn id(&self) -> &str {
+        "bookmarks"
+    }
+
+    fn section(&self) -> &str {
+        "Web"
+    }
+
+    fn modes(&self) -> &[QueryMode] {
+        &[QueryMode::BrowserHistory, QueryMode::Search]
+    }
+
+    fn query(&self, query: &Query, filters: &[QueryFilter]) -> Vec<ProviderResult> {
+        if is_empty_query(query) || !filters.is_empty() {
+            return vec![];
+        }
+        let bookmarks = match self.bookmarks.lock() {
+            Ok(bookmarks) => bookmarks.query(query).unwrap_or_default(),
+            Err(_) => return vec![],
+        };
+        bookmarks
+            .iter()
+            .enumerate()
+            .map(|(rank, item)| ProviderResult {
+                key: normalize_url(&item.url),
+                ..ProviderResult::ranked(bookmark_result(item), rank)
+            })
+            .collect()
+    }
+}
+
+struct BrowserHistoryProvider {
+    browser_history: Mutex<BrowserHistoryDataSource>,
+}
+
+impl ResultProvider for BrowserHistoryProvider {
+    fn id(&self) -> &str {
+        "browser_history"
+    }
+
+    fn section(&self) -> &str {
+        "Web"
+    }
+
+    fn modes(&self) -> &[QueryMode] {
+        &[QueryMode::BrowserHistory, QueryMode::Search]
+    }
+
+    fn query(&self, query: &Query, filters: &[QueryFilter]) -> Vec<ProviderResult> {
+        if is_empty_query(query) || !filters.is_empty() {
+            return vec![];
+        }
+        let history = match self.browser_history.lock() {
+            Ok(browser_history) => browser_history.query(query).unwrap_or_default(),
+            Err(_) => return vec![],
+        };
+        // history urls are stored normalized already
+        history
+            .iter()
+            .enumerate()
+            .map(|(rank, item)| ProviderResult {
+                key: item.url.clone(),
+                ..ProviderResult::ranked(history_result(item), rank)
+            })
+            .collect()
+    }
+}
+
+//* The scripts in swordfish's scripts folder, all of them in the scripts mode and the ones matching by name in the search */
+struct ScriptsProvider;
+
+// the folder is small and read again for every query, so new scripts show up right away
+fn read_scripts() -> Vec<PathBuf> {
+    let entries = match get_scripts_path().map(fs::read_dir) {
+        Some(Ok(entries)) => entries,
+        _ => return vec![],
+    };
+    let mut scripts: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_file())
+        .collect();
+    scripts.sort();
+    scripts
+}
+
+fn script_result(path: &Path) -> ResultItem {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let last_modified = fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .map(|modified| {
+            DateTime::<Local>::from(modified)
+                .format("%Y-%m-%d %H:%M")
+                .to_string()
         })
-        .collect::<Vec<_>>()
-        .join("");
-    format!("<span class=\"calculator\">{}</span>", calculator_content)
+        .unwrap_or_default();
+    ResultItem {
+        heading: name.clone(),
+        subheading: "Run script".to_string(),
+        value: name,
+        icon_path: None,
+        r#type: ResultType::Script,
+        details: Some(ResultDetails::Script {
+            path: path.to_string_lossy().to_string(),
+            last_modified,
+            language: path
+                .extension()
+                .map(|extension| extension.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            content: "".to_string(),
+            parsed_content: fs::read_to_string(path).ok(),
+        }),
+    }
+}
+
+impl ResultProvider for ScriptsProvider {
+    fn id(&self) -> &str {
+        "scripts"
+    }
+
+    fn section(&self) -> &str {
+        "Scripts"
+    }
+
+    fn modes(&self) -> &[QueryMode] {
+        &[QueryMode::Scripts, QueryMode::Search]
+    }
+
+    fn query(&self, query: &Query, filters: &[QueryFilter]) -> Vec<ProviderResult> {
+        let term = query.search_string.trim();
+        // the scripts mode lists every script until something is typed, the blended
+        // search only ever the matching ones
+        if !filters.is_empty() || (query.mode == QueryMode::Search && term.is_empty()) {
+            return vec![];
+        }
+        let matcher = SkimMatcherV2::default().ignore_case();
+        let mut scripts: Vec<(i64, PathBuf)> = read_scripts()
+            .into_iter()
+            .filter_map(|path| {
+                if term.is_empty() {
+                    return Some((0, path));
+                }
+                let name = path.file_name()?.to_string_lossy().to_string();
+                matcher.fuzzy_match(&name, term).map(|score| (score, path))
+            })
+            .collect();
+        scripts.sort_by(|a, b| b.0.cmp(&a.0));
+        scripts
+            .iter()
+            .enumerate()
+            .map(|(rank, (_, path))| ProviderResult {
+                key: format!("script:{}", path.to_string_lossy()),
+                ..ProviderResult::ranked(script_result(path), rank)
+            })
+            .collect()
+    }
+}
+
+fn history_result(item: &HistoryEntry) -> ResultItem {
+    let subheading = format!("{} · {}", item.source_label(), item.original_url);
+    ResultItem {
+        heading: item.title.clone(),
+        subheading: subheading.clone(),
+        value: item.original_url.clone(),
+        details: Some(ResultDetails::BrowserHistory {
+            url: item.original_url.clone(),
+            image_url: "".to_string(),
+            heading: item.title.clone(),
+            subheading,
+            source: item.source_label(),
+        }),
+        icon_path: get_favicon_path(item.url.as_str()),
+        r#type: ResultType::BrowserHistory,
+    }
+}
+
+fn open_tab_result(item: &OpenTab) -> ResultItem {
+    ResultItem {
+        heading: item.title.clone(),
+        subheading: format!("Open tab in {} · {}", item.source_label(), item.url),
+        value: item.url.clone(),
+        details: Some(ResultDetails::OpenTab {
+            url: item.url.clone(),
+            heading: item.title.clone(),
+            source: item.source_label(),
+        }),
+        icon_path: get_favicon_path(item.url.as_str()),
+        r#type: ResultType::OpenTab,
+    }
+}
+
+fn bookmark_result(item: &Bookmark) -> ResultItem {
+    ResultItem {
+        heading: item.title.clone(),
+        subheading: format!("{} · {}", item.folder, item.url),
+        value: item.url.clone(),
+        details: Some(ResultDetails::Bookmark {
+            url: item.url.clone(),
+            heading: item.title.clone(),
+            folder: item.folder.clone(),
+            source: item.source_label(),
+        }),
+        icon_path: get_favicon_path(item.url.as_str()),
+        r#type: ResultType::Bookmark,
+    }
 }
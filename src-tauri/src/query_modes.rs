@@ -0,0 +1,22 @@
+use swordfish_types::Query;
+
+use crate::settings::ModePrefix;
+
+//* Switches to the mode of the longest prefix the search string starts with, and drops the prefix from it */
+pub fn apply_mode_prefix(query: Query, mode_prefixes: &[ModePrefix]) -> Query {
+    let matched = mode_prefixes
+        .iter()
+        .filter(|mode_prefix| {
+            !mode_prefix.prefix.is_empty() && query.search_string.starts_with(&mode_prefix.prefix)
+        })
+        .max_by_key(|mode_prefix| mode_prefix.prefix.len());
+    match matched {
+        Some(mode_prefix) => Query {
+            search_string: query.search_string[mode_prefix.prefix.len()..]
+                .trim_start()
+                .to_string(),
+            mode: mode_prefix.mode.clone(),
+        },
+        None => query,
+    }
+}
@@ -0,0 +1,428 @@
+use chrono::{Local, NaiveDate, TimeZone};
+use serde_variant::to_variant_name;
+use swordfish_types::{FileType, FilterCondition, QueryFilter};
+
+//* A search string split into the text that's fuzzy matched and the filters around it */
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParsedQuery {
+    pub term: String,
+    pub filters: Vec<QueryFilter>,
+}
+
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+
+// "code" and the like cover several file types
+const FILE_TYPE_ALIASES: &[(&str, &[FileType])] = &[
+    ("dir", &[FileType::Directory]),
+    ("folder", &[FileType::Directory]),
+    ("app", &[FileType::Application]),
+    ("doc", &[FileType::Word, FileType::Pdf, FileType::Text]),
+    ("sheet", &[FileType::Excel, FileType::CSV]),
+    ("slides", &[FileType::Powerpoint]),
+    (
+        "code",
+        &[
+            FileType::Rust,
+            FileType::Typescript,
+            FileType::Javascript,
+            FileType::Python,
+            FileType::C,
+            FileType::CHeader,
+            FileType::Cpp,
+            FileType::Java,
+            FileType::Go,
+            FileType::Swift,
+            FileType::Lua,
+        ],
+    ),
+];
+
+// splits on whitespace, except inside double quotes
+fn tokenize(search_string: &str) -> Vec<String> {
+    let mut tokens = vec![];
+    let mut token = String::new();
+    let mut quoted = false;
+    for c in search_string.chars() {
+        match c {
+            // the quotes only group words, they aren't part of the term
+            '"' => quoted = !quoted,
+            c if c.is_whitespace() && !quoted => {
+                if !token.is_empty() {
+                    tokens.push(std::mem::take(&mut token));
+                }
+            }
+            c => token.push(c),
+        }
+    }
+    if !token.is_empty() {
+        tokens.push(token);
+    }
+    tokens
+}
+
+fn split_comparison(value: &str) -> (&str, &str) {
+    for operator in ["<=", ">=", "<", ">", "="] {
+        if let Some(rest) = value.strip_prefix(operator) {
+            return (operator, rest.trim());
+        }
+    }
+    ("", value)
+}
+
+fn parse_file_types(value: &str) -> Result<Vec<FileType>, String> {
+    let mut file_types = vec![];
+    for name in value.split(',').filter(|name| !name.is_empty()) {
+        let name = name.to_lowercase();
+        if let Some((_, aliased)) = FILE_TYPE_ALIASES.iter().find(|(alias, _)| *alias == name) {
+            file_types.extend_from_slice(aliased);
+            continue;
+        }
+        // otherwise the name of the FileType itself, e.g. "image", "csv" or "shellscript"
+        let file_type = FileType::ALL.iter().find(|file_type| {
+            to_variant_name(*file_type).map_or(false, |variant| variant.eq_ignore_ascii_case(&name))
+        });
+        match file_type {
+            Some(file_type) => file_types.push(file_type.clone()),
+            None => return Err(format!("'{}' is not a known file type", name)),
+        }
+    }
+    Ok(file_types)
+}
+
+fn parse_duration(value: &str) -> Option<i64> {
+    let unit_start = value.find(|c: char| !c.is_ascii_digit())?;
+    let amount: i64 = value[..unit_start].parse().ok()?;
+    let unit = match &value[unit_start..] {
+        "h" => 60 * 60,
+        "d" => SECONDS_PER_DAY,
+        "w" => 7 * SECONDS_PER_DAY,
+        "m" => 30 * SECONDS_PER_DAY,
+        "y" => 365 * SECONDS_PER_DAY,
+        _ => return None,
+    };
+    Some(amount * unit)
+}
+
+//* "<7d" is younger than 7 days, ">2024-01-01" is after that day and "2024-01-01" is on it */
+fn parse_time_range(value: &str, now: i64) -> Result<(Option<i64>, Option<i64>), String> {
+    let (operator, value) = split_comparison(value);
+    if let Some(age) = parse_duration(value) {
+        let threshold = now - age;
+        return Ok(match operator {
+            ">" | ">=" => (None, Some(threshold)),
+            _ => (Some(threshold), None),
+        });
+    }
+    let day_start = NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .ok()
+        .and_then(|date| date.and_hms_opt(0, 0, 0))
+        .and_then(|date| Local.from_local_datetime(&date).earliest())
+        .map(|date| date.timestamp())
+        .ok_or_else(|| {
+            format!(
+                "'{}' is neither an age like 7d nor a date like 2024-01-31",
+                value
+            )
+        })?;
+    let day_end = day_start + SECONDS_PER_DAY;
+    Ok(match operator {
+        "<" => (None, Some(day_start)),
+        "<=" => (None, Some(day_end)),
+        ">" => (Some(day_end), None),
+        ">=" => (Some(day_start), None),
+        _ => (Some(day_start), Some(day_end)),
+    })
+}
+
+fn parse_size(value: &str) -> Result<(Option<u64>, Option<u64>), String> {
+    let (operator, value) = split_comparison(value);
+    let value = value.to_lowercase();
+    let unit_start = value
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(value.len());
+    let multiplier: u64 = match &value[unit_start..] {
+        "" | "b" => 1,
+        "kb" | "k" => 1 << 10,
+        "mb" | "m" => 1 << 20,
+        "gb" | "g" => 1 << 30,
+        "tb" | "t" => 1 << 40,
+        unit => return Err(format!("'{}' is not a size unit, use kb, mb or gb", unit)),
+    };
+    let amount: f64 = value[..unit_start]
+        .parse()
+        .map_err(|_| format!("'{}' is not a size like 10mb", value))?;
+    let bytes = (amount * multiplier as f64) as u64;
+    match operator {
+        ">" => Ok((Some(bytes + 1), None)),
+        ">=" => Ok((Some(bytes), None)),
+        "<" => Ok((None, Some(bytes.saturating_sub(1)))),
+        "<=" => Ok((None, Some(bytes))),
+        _ => Err(format!("Use size:>{0} or size:<{0}", value)),
+    }
+}
+
+fn expand_home(path: &str) -> String {
+    match dirs::home_dir() {
+        Some(home) if path == "~" || path.starts_with("~/") || path.starts_with("~\\") => {
+            path.replacen('~', &home.to_string_lossy(), 1)
+        }
+        _ => path.to_string(),
+    }
+}
+
+// None when the token isn't a filter and belongs to the search term
+fn parse_filter(token: &str, now: i64) -> Option<QueryFilter> {
+    let (key, value) = token.split_once(':')?;
+    if value.is_empty() {
+        return None;
+    }
+    let condition = match key.to_lowercase().as_str() {
+        "ext" => Ok(FilterCondition::Extension {
+            extensions: value
+                .split(',')
+                .filter(|extension| !extension.is_empty())
+                .map(|extension| extension.trim_start_matches('.').to_lowercase())
+                .collect(),
+        }),
+        "type" => {
+            parse_file_types(value).map(|file_types| FilterCondition::FileType { file_types })
+        }
+        "in" => Ok(FilterCondition::Path {
+            path: expand_home(value),
+        }),
+        "modified" => parse_time_range(value, now)
+            .map(|(after, before)| FilterCondition::Modified { after, before }),
+        "created" => parse_time_range(value, now)
+            .map(|(after, before)| FilterCondition::Created { after, before }),
+        "size" => parse_size(value).map(|(min, max)| FilterCondition::Size { min, max }),
+        _ => return None,
+    };
+    Some(QueryFilter {
+        text: token.to_string(),
+        condition: condition.unwrap_or_else(|message| FilterCondition::Invalid { message }),
+    })
+}
+
+//* Parses filters like "ext:rs", "type:image", "in:~/Documents", "modified:<7d" or "size:>10mb" out of a search string */
+pub fn parse_query(search_string: &str) -> ParsedQuery {
+    let now = Local::now().timestamp();
+    let mut terms = vec![];
+    let mut filters = vec![];
+    for token in tokenize(search_string) {
+        match parse_filter(&token, now) {
+            Some(filter) => filters.push(filter),
+            None => terms.push(token),
+        }
+    }
+    ParsedQuery {
+        term: terms.join(" "),
+        filters,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: i64 = 1_700_000_000;
+
+    fn condition(token: &str) -> FilterCondition {
+        parse_filter(token, NOW).unwrap().condition
+    }
+
+    fn day_start(date: &str) -> i64 {
+        let date = NaiveDate::parse_from_str(date, "%Y-%m-%d").unwrap();
+        Local
+            .from_local_datetime(&date.and_hms_opt(0, 0, 0).unwrap())
+            .earliest()
+            .unwrap()
+            .timestamp()
+    }
+
+    #[test]
+    fn splits_filters_from_the_term() {
+        let parsed = parse_query("quarterly ext:pdf report size:>1mb");
+        assert_eq!(parsed.term, "quarterly report");
+        assert_eq!(
+            parsed
+                .filters
+                .iter()
+                .map(|filter| filter.text.as_str())
+                .collect::<Vec<_>>(),
+            vec!["ext:pdf", "size:>1mb"]
+        );
+    }
+
+    #[test]
+    fn keeps_unknown_keys_and_empty_values_in_the_term() {
+        let parsed = parse_query("http://localhost:3000 note: ext:");
+        assert_eq!(parsed.term, "http://localhost:3000 note: ext:");
+        assert!(parsed.filters.is_empty());
+    }
+
+    #[test]
+    fn strips_quotes_from_the_term() {
+        let parsed = parse_query("\"my notes\" type:pdf");
+        assert_eq!(parsed.term, "my notes");
+        assert_eq!(parsed.filters.len(), 1);
+    }
+
+    #[test]
+    fn quoted_filter_values_keep_their_spaces() {
+        let parsed = parse_query("in:\"/tmp/my docs\" budget");
+        assert_eq!(parsed.term, "budget");
+        assert_eq!(
+            parsed.filters[0].condition,
+            FilterCondition::Path {
+                path: "/tmp/my docs".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn parses_extensions() {
+        assert_eq!(
+            condition("ext:.RS,toml,"),
+            FilterCondition::Extension {
+                extensions: vec!["rs".to_string(), "toml".to_string()]
+            }
+        );
+    }
+
+    #[test]
+    fn parses_file_type_names_case_insensitively() {
+        for (name, file_type) in [
+            ("csv", FileType::CSV),
+            ("pkg", FileType::PKG),
+            ("markdownx", FileType::MarkdownX),
+            ("ebook", FileType::EBook),
+            ("cheader", FileType::CHeader),
+            ("VectorImage", FileType::VectorImage),
+            ("shellscript", FileType::ShellScript),
+            ("diskimage", FileType::DiskImage),
+            ("IMAGE", FileType::Image),
+        ] {
+            assert_eq!(
+                condition(&format!("type:{}", name)),
+                FilterCondition::FileType {
+                    file_types: vec![file_type]
+                },
+                "type:{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn expands_file_type_aliases() {
+        assert_eq!(
+            condition("type:folder,sheet"),
+            FilterCondition::FileType {
+                file_types: vec![FileType::Directory, FileType::Excel, FileType::CSV]
+            }
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_file_types() {
+        assert!(matches!(
+            condition("type:spreadsheet"),
+            FilterCondition::Invalid { .. }
+        ));
+    }
+
+    #[test]
+    fn parses_ages() {
+        assert_eq!(
+            condition("modified:<7d"),
+            FilterCondition::Modified {
+                after: Some(NOW - 7 * SECONDS_PER_DAY),
+                before: None
+            }
+        );
+        assert_eq!(
+            condition("created:>2h"),
+            FilterCondition::Created {
+                after: None,
+                before: Some(NOW - 2 * 60 * 60)
+            }
+        );
+        assert_eq!(
+            condition("modified:1w"),
+            FilterCondition::Modified {
+                after: Some(NOW - 7 * SECONDS_PER_DAY),
+                before: None
+            }
+        );
+        assert_eq!(parse_duration("3m"), Some(90 * SECONDS_PER_DAY));
+        assert_eq!(parse_duration("1y"), Some(365 * SECONDS_PER_DAY));
+        assert_eq!(parse_duration("7x"), None);
+        assert_eq!(parse_duration("d"), None);
+    }
+
+    #[test]
+    fn parses_dates_with_each_operator() {
+        let start = day_start("2024-01-31");
+        let end = start + SECONDS_PER_DAY;
+        for (operator, after, before) in [
+            ("<", None, Some(start)),
+            ("<=", None, Some(end)),
+            (">", Some(end), None),
+            (">=", Some(start), None),
+            ("", Some(start), Some(end)),
+            ("=", Some(start), Some(end)),
+        ] {
+            assert_eq!(
+                condition(&format!("modified:{}2024-01-31", operator)),
+                FilterCondition::Modified { after, before },
+                "modified:{}2024-01-31",
+                operator
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_dates() {
+        assert!(matches!(
+            condition("modified:2024-13-01"),
+            FilterCondition::Invalid { .. }
+        ));
+        assert!(matches!(
+            condition("created:yesterday"),
+            FilterCondition::Invalid { .. }
+        ));
+    }
+
+    #[test]
+    fn parses_sizes_with_each_operator() {
+        for (value, min, max) in [
+            (">10mb", Some((10 << 20) + 1), None),
+            (">=10MB", Some(10 << 20), None),
+            ("<1k", None, Some(1023)),
+            ("<=1.5kb", None, Some(1536)),
+            (">2g", Some((2 << 30) + 1), None),
+            ("<=100", None, Some(100)),
+        ] {
+            assert_eq!(
+                condition(&format!("size:{}", value)),
+                FilterCondition::Size { min, max },
+                "size:{}",
+                value
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_sizes() {
+        for value in ["10mb", ">10parsecs", ">big"] {
+            assert!(
+                matches!(
+                    condition(&format!("size:{}", value)),
+                    FilterCondition::Invalid { .. }
+                ),
+                "size:{}",
+                value
+            );
+        }
+    }
+}
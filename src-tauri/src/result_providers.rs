@@ -0,0 +1,308 @@
+use std::collections::{BTreeMap, HashSet};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use swordfish_types::{Query, QueryFilter, QueryMode, ResultItem, ResultSection};
+
+use crate::settings::ProviderSettings;
+
+//* A result and how well it matched, `key` is what duplicates across providers are recognized by */
+pub struct ProviderResult {
+    pub item: ResultItem,
+    /* higher is better, only comparable between results of the same provider until
+    the registry scales it relative to the provider's best result */
+    pub score: f64,
+    pub key: String,
+}
+
+impl ProviderResult {
+    //* For providers that only know the order of their results */
+    pub fn ranked(item: ResultItem, rank: usize) -> Self {
+        Self {
+            key: item.value.clone(),
+            score: 1.0 / (rank + 1) as f64,
+            item,
+        }
+    }
+}
+
+//* Anything that turns a query into results, e.g. the file index or the browser history */
+pub trait ResultProvider: Send + Sync {
+    //* The key of its settings in config.json */
+    fn id(&self) -> &str;
+    //* Title of the section its results are listed in, providers sharing a section have their results mixed */
+    fn section(&self) -> &str;
+    fn modes(&self) -> &[QueryMode];
+    //* In Search mode `query.search_string` is what's left once `filters` are parsed out of it */
+    fn query(&self, query: &Query, filters: &[QueryFilter]) -> Vec<ProviderResult>;
+    //* What the registry calls, `timeout` is how long it waits for the results. Only worth implementing for providers that can give up on their own */
+    fn query_within(
+        &self,
+        query: &Query,
+        filters: &[QueryFilter],
+        _timeout: Duration,
+    ) -> Vec<ProviderResult> {
+        self.query(query, filters)
+    }
+}
+
+//* Results of every provider that answered, grouped into sections */
+#[derive(Default)]
+pub struct MergedResults {
+    pub results: Vec<ResultItem>,
+    pub sections: Vec<ResultSection>,
+}
+
+// results of the providers sharing a section, along with their priority and
+// registration index
+struct Section<'a> {
+    title: &'a str,
+    priority: i32,
+    results: Vec<(i32, usize, ProviderResult)>,
+}
+
+type ResultSender = mpsc::Sender<(usize, Vec<ProviderResult>)>;
+
+// one query for a provider and where its results go
+struct ProviderJob {
+    query: Query,
+    filters: Vec<QueryFilter>,
+    timeout: Duration,
+    sender: ResultSender,
+}
+
+// A provider is queried by at most one thread at a time. Queries arriving while it's
+// busy wait in `queued`, where the newest replaces any that wasn't started yet, and
+// run as soon as the thread is done with the one before.
+#[derive(Default)]
+struct ProviderSlot {
+    busy: bool,
+    queued: Option<ProviderJob>,
+}
+
+// frees the slot of a provider that panicked, so it isn't busy forever
+struct PanicGuard(Arc<Mutex<ProviderSlot>>);
+
+impl Drop for PanicGuard {
+    fn drop(&mut self) {
+        if thread::panicking() {
+            if let Ok(mut slot) = self.0.lock() {
+                slot.busy = false;
+                slot.queued = None;
+            }
+        }
+    }
+}
+
+fn run_provider(
+    index: usize,
+    provider: Arc<dyn ResultProvider>,
+    slot: Arc<Mutex<ProviderSlot>>,
+    mut job: ProviderJob,
+) {
+    let _guard = PanicGuard(Arc::clone(&slot));
+    loop {
+        let results = provider.query_within(&job.query, &job.filters, job.timeout);
+        // the receiver is gone when the provider took too long
+        job.sender.send((index, results)).ok();
+        job = match slot.lock() {
+            Ok(mut slot) => match slot.queued.take() {
+                Some(next) => next,
+                None => {
+                    slot.busy = false;
+                    return;
+                }
+            },
+            Err(_) => return,
+        };
+    }
+}
+
+#[derive(Default)]
+pub struct ProviderRegistry {
+    providers: Vec<Arc<dyn ResultProvider>>,
+    /* by the index of the provider */
+    slots: Vec<Arc<Mutex<ProviderSlot>>>,
+}
+
+impl ProviderRegistry {
+    pub fn new() -> Self {
+        Self {
+            providers: vec![],
+            slots: vec![],
+        }
+    }
+
+    pub fn register(&mut self, provider: Arc<dyn ResultProvider>) {
+        self.providers.push(provider);
+        self.slots
+            .push(Arc::new(Mutex::new(ProviderSlot::default())));
+    }
+
+    //* Queries the enabled providers of the query's mode in parallel and merges what they return before their timeout. A provider still busy with an earlier query answers this one right after, instead of a second thread waiting on it. */
+    pub fn query(
+        &self,
+        query: &Query,
+        filters: &[QueryFilter],
+        settings: &BTreeMap<String, ProviderSettings>,
+    ) -> MergedResults {
+        let start = Instant::now();
+        let (sender, receiver) = mpsc::channel();
+        let mut pending: Vec<(usize, ProviderSettings)> = vec![];
+        for (index, provider) in self.providers.iter().enumerate() {
+            let provider_settings = settings.get(provider.id()).cloned().unwrap_or_default();
+            if !provider_settings.enabled || !provider.modes().contains(&query.mode) {
+                continue;
+            }
+            let job = ProviderJob {
+                query: query.clone(),
+                filters: filters.to_vec(),
+                timeout: Duration::from_millis(provider_settings.timeout_ms),
+                sender: sender.clone(),
+            };
+            pending.push((index, provider_settings));
+
+            let slot = Arc::clone(&self.slots[index]);
+            let mut locked = match slot.lock() {
+                Ok(locked) => locked,
+                Err(_) => continue,
+            };
+            // the thread answering an earlier query is usually holding the provider's lock
+            if locked.busy {
+                locked.queued = Some(job);
+                continue;
+            }
+            locked.busy = true;
+            drop(locked);
+            let provider = Arc::clone(provider);
+            thread::spawn(move || run_provider(index, provider, slot, job));
+        }
+        drop(sender);
+
+        let mut answered: Vec<(usize, ProviderSettings, Vec<ProviderResult>)> = vec![];
+        while !pending.is_empty() {
+            let deadline = pending
+                .iter()
+                .map(|(_, settings)| start + Duration::from_millis(settings.timeout_ms))
+                .max()
+                .unwrap_or(start);
+            let (index, results) =
+                match receiver.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                    Ok(received) => received,
+                    Err(_) => break,
+                };
+            let position = match pending.iter().position(|(pending, _)| *pending == index) {
+                Some(position) => position,
+                None => continue,
+            };
+            let (_, provider_settings) = pending.remove(position);
+            if start.elapsed() > Duration::from_millis(provider_settings.timeout_ms) {
+                eprintln!(
+                    "Ignoring the results of '{}', it took longer than {}ms",
+                    self.providers[index].id(),
+                    provider_settings.timeout_ms
+                );
+                continue;
+            }
+            answered.push((index, provider_settings, results));
+        }
+        for (index, provider_settings) in pending.iter() {
+            eprintln!(
+                "'{}' didn't answer within {}ms",
+                self.providers[*index].id(),
+                provider_settings.timeout_ms
+            );
+        }
+
+        self.merge(answered)
+    }
+
+    // Each provider's best results are kept and scaled so its top result scores 1,
+    // which lets results of providers sharing a section be mixed. Sections are
+    // ordered by the highest priority among their providers, and ties in score go
+    // to the higher priority provider and then the one registered first, so the
+    // order doesn't depend on which thread finished first.
+    fn merge(
+        &self,
+        answered: Vec<(usize, ProviderSettings, Vec<ProviderResult>)>,
+    ) -> MergedResults {
+        let mut sections: Vec<Section> = vec![];
+        for (index, provider_settings, mut results) in answered {
+            results.sort_by(|a, b| b.score.total_cmp(&a.score));
+            results.truncate(provider_settings.max_results);
+            normalize_scores(&mut results);
+
+            let title = self.providers[index].section();
+            let position = match sections.iter().position(|section| section.title == title) {
+                Some(position) => position,
+                None => {
+                    sections.push(Section {
+                        title,
+                        priority: provider_settings.priority,
+                        results: vec![],
+                    });
+                    sections.len() - 1
+                }
+            };
+            let section = &mut sections[position];
+            section.priority = section.priority.max(provider_settings.priority);
+            section.results.extend(
+                results
+                    .into_iter()
+                    .map(|result| (provider_settings.priority, index, result)),
+            );
+        }
+        let first_index =
+            |section: &Section| section.results.iter().map(|(_, index, _)| *index).min();
+        sections.sort_by(|a, b| {
+            b.priority
+                .cmp(&a.priority)
+                .then_with(|| first_index(a).cmp(&first_index(b)))
+        });
+
+        // a result several providers know of is listed once, where it's listed first
+        let mut seen_keys: HashSet<String> = HashSet::new();
+        let mut merged = MergedResults::default();
+        for Section {
+            title, mut results, ..
+        } in sections
+        {
+            results.sort_by(|a, b| {
+                b.2.score
+                    .total_cmp(&a.2.score)
+                    .then_with(|| b.0.cmp(&a.0))
+                    .then_with(|| a.1.cmp(&b.1))
+            });
+            let start = merged.results.len();
+            merged.results.extend(
+                results
+                    .into_iter()
+                    .filter(|(_, _, result)| seen_keys.insert(result.key.clone()))
+                    .map(|(_, _, result)| result.item),
+            );
+            if merged.results.len() > start {
+                merged.sections.push(ResultSection {
+                    title: title.to_string(),
+                    start,
+                    count: merged.results.len() - start,
+                });
+            }
+        }
+        merged
+    }
+}
+
+// scales the scores of results sorted best first so the best one scores 1, providers
+// whose scores aren't all positive are ranked by position instead
+fn normalize_scores(results: &mut [ProviderResult]) {
+    let best = results.first().map(|result| result.score).unwrap_or(0.0);
+    let all_positive = results.iter().all(|result| result.score > 0.0);
+    for (rank, result) in results.iter_mut().enumerate() {
+        result.score = if all_positive {
+            result.score / best
+        } else {
+            1.0 / (rank + 1) as f64
+        };
+    }
+}
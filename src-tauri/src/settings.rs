@@ -1,139 +1,948 @@
 use std::{
-    fs::File,
-    io::{Read, Write},
+    collections::BTreeMap,
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
 };
 
+use chrono::Local;
 use dirs::home_dir;
+use globset::Glob;
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use swordfish_types::{FileType, QueryMode};
 use swordfish_utilities::config_filepath;
+use tauri_plugin_global_shortcut::Shortcut;
+use thiserror::Error;
+use ts_rs::TS;
+use url::Url;
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+use crate::browser_data_source::Browser;
+use crate::history_privacy::DomainPattern;
+use crate::plugin_host::PLUGIN_PROVIDER_PREFIX;
+use crate::shortcuts::{parse_shortcut, same_keys};
+
+//* Bumped whenever the layout of config.json changes, along with a migration from the previous version */
+pub const CONFIG_VERSION: u32 = 9;
+
+#[derive(TS, Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[ts(export, export_to = "../../src/types/")]
+#[serde(default)]
 pub struct AppConfig {
+    pub version: u32,
     pub launch_shortcut: String,
-    pub search_directories: Vec<String>,
+    pub search_directories: Vec<SearchDirectory>,
+    pub browser_history: BrowserHistoryConfig,
+    pub keybindings: Vec<Keybinding>,
+    pub mode_prefixes: Vec<ModePrefix>,
+    /* keyed by provider, e.g. "files" or "browser_history" */
+    pub providers: BTreeMap<String, ProviderSettings>,
+    pub calculator: CalculatorConfig,
+}
+
+//* A directory to index, along with what in it ends up in the index */
+#[derive(TS, Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[ts(export, export_to = "../../src/types/")]
+#[serde(default)]
+pub struct SearchDirectory {
+    /* absolute, or starting with ~ */
+    pub path: String,
+    /* how many levels below `path` are walked, no limit when null */
+    pub max_depth: Option<usize>,
+    /* globs relative to `path`, ones without a slash like "*.log" match at any depth. When non-empty only matching files are indexed */
+    pub include: Vec<String>,
+    /* globs for files and directories that are skipped, matched like `include` */
+    pub exclude: Vec<String>,
+    pub respect_gitignore: bool,
+    pub include_hidden: bool,
+    /* when non-empty, only files of these types are indexed */
+    pub file_types: Vec<FileType>,
+}
+
+impl SearchDirectory {
+    pub fn new(path: &str) -> Self {
+        Self {
+            path: path.to_string(),
+            ..Self::default()
+        }
+    }
+}
+
+impl Default for SearchDirectory {
+    fn default() -> Self {
+        Self {
+            path: String::new(),
+            max_depth: Some(6),
+            include: vec![],
+            exclude: get_default_exclusions(),
+            respect_gitignore: true,
+            include_hidden: true,
+            file_types: vec![],
+        }
+    }
+}
+
+//* A global shortcut and what it does */
+#[derive(TS, Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[ts(export, export_to = "../../src/types/")]
+pub struct Keybinding {
+    pub shortcut: String,
+    pub action: KeybindingAction,
+}
+
+#[derive(TS, Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[ts(export, export_to = "../../src/types/")]
+#[serde(tag = "type")]
+pub enum KeybindingAction {
+    /* shows the main window in the given mode */
+    OpenQueryMode { mode: QueryMode },
+    /* the name of a file in the scripts directory */
+    RunScript { script_name: String },
+    OpenFile { path: String },
+}
+
+//* Typing `prefix` at the start of the search box searches in `mode` instead, e.g. "> " for scripts */
+#[derive(TS, Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[ts(export, export_to = "../../src/types/")]
+pub struct ModePrefix {
+    /* matched as typed, so "h " needs the space */
+    pub prefix: String,
+    pub mode: QueryMode,
+}
+
+impl ModePrefix {
+    fn new(prefix: &str, mode: QueryMode) -> Self {
+        Self {
+            prefix: prefix.to_string(),
+            mode,
+        }
+    }
+}
+
+//* How a result provider takes part in queries */
+#[derive(TS, Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[ts(export, export_to = "../../src/types/")]
+#[serde(default)]
+pub struct ProviderSettings {
+    pub enabled: bool,
+    /* results of higher priority providers are listed first */
+    pub priority: i32,
+    /* results arriving later than this are dropped */
+    #[ts(type = "number")]
+    pub timeout_ms: u64,
+    /* results past this many are dropped, so one provider can't crowd out the others */
+    pub max_results: usize,
+}
+
+impl ProviderSettings {
+    fn new(priority: i32, timeout_ms: u64, max_results: usize) -> Self {
+        Self {
+            enabled: true,
+            priority,
+            timeout_ms,
+            max_results,
+        }
+    }
+}
+
+impl Default for ProviderSettings {
+    fn default() -> Self {
+        Self::new(0, 1000, 20)
+    }
 }
 
-fn get_default_search_directories() -> Vec<String> {
-    let home_path = home_dir().expect("couldn't find the home dir!");
-    let home_path = home_path
-        .to_str()
-        .expect("Could convert the home directory path to a string!");
+//* Which browsers and domains end up in the browser history index, and for how long */
+#[derive(TS, Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[ts(export, export_to = "../../src/types/")]
+#[serde(default)]
+pub struct BrowserHistoryConfig {
+    pub disabled_browsers: Vec<Browser>,
+    /* when non-empty, only these domains are indexed */
+    pub allowed_domains: Vec<String>,
+    /* "example.com" (and its subdomains), a glob like "*.example.*" or a "/regex/" */
+    pub denied_domains: Vec<String>,
+    pub retention_days: u32,
+    /* asks Google's favicon service for icons the browsers don't have, which sends it the domains */
+    pub fetch_missing_favicons: bool,
+}
 
+impl Default for BrowserHistoryConfig {
+    fn default() -> Self {
+        Self {
+            disabled_browsers: vec![],
+            allowed_domains: vec![],
+            denied_domains: vec![
+                "localhost".to_string(),
+                "127.0.0.1".to_string(),
+                "0.0.0.0".to_string(),
+            ],
+            retention_days: 180,
+            fetch_missing_favicons: false,
+        }
+    }
+}
+
+//* Where the calculator gets the exchange rates it converts currencies with */
+#[derive(TS, Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[ts(export, export_to = "../../src/types/")]
+#[serde(default)]
+pub struct CalculatorConfig {
+    /* returns JSON like {"base": "EUR", "date": "2024-08-23", "rates": {"USD": 1.1187}}, fetched twice a day */
+    pub exchange_rates_url: String,
+}
+
+impl Default for CalculatorConfig {
+    fn default() -> Self {
+        Self {
+            exchange_rates_url: "https://api.frankfurter.app/latest".to_string(),
+        }
+    }
+}
+
+//* A problem with one setting, `field` is its path in config.json, e.g. "browser_history.denied_domains[2]" */
+#[derive(TS, Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[ts(export, export_to = "../../src/types/")]
+pub struct ConfigError {
+    pub field: String,
+    pub message: String,
+}
+
+impl ConfigError {
+    pub fn new(field: &str, message: String) -> Self {
+        Self {
+            field: field.to_string(),
+            message,
+        }
+    }
+}
+
+//* The config in use and everything that was wrong with the file it came from */
+#[derive(TS, Serialize, Deserialize, Debug, Clone)]
+#[ts(export, export_to = "../../src/types/")]
+pub struct SettingsState {
+    pub config: AppConfig,
+    pub errors: Vec<ConfigError>,
+}
+
+#[derive(Error, Debug)]
+enum ConfigParseError {
+    #[error("config.json is not valid: {0}")]
+    Invalid(#[from] serde_json::Error),
+    #[error("config.json must contain a JSON object")]
+    NotAnObject,
+    #[error("config.json has an invalid version")]
+    InvalidVersion,
+    #[error("config.json was written by a newer version of swordfish (config version {0})")]
+    UnsupportedVersion(u64),
+}
+
+// each migration upgrades a config from the version before it
+const MIGRATIONS: &[(u32, fn(&mut Map<String, Value>))] = &[
+    (2, migrate_to_v2),
+    (3, migrate_to_v3),
+    (4, migrate_to_v4),
+    (5, migrate_to_v5),
+    (6, migrate_to_v6),
+    (7, migrate_to_v7),
+    (8, migrate_to_v8),
+    (9, migrate_to_v9),
+];
+
+// version 1 files had no version and no browser history settings
+fn migrate_to_v2(config: &mut Map<String, Value>) {
+    config.entry("browser_history").or_insert_with(|| {
+        serde_json::to_value(BrowserHistoryConfig::default()).unwrap_or_default()
+    });
+}
+
+// version 2 files had no keybindings besides the launch shortcut
+fn migrate_to_v3(config: &mut Map<String, Value>) {
+    config
+        .entry("keybindings")
+        .or_insert_with(|| Value::Array(vec![]));
+}
+
+// version 3 search directories were plain paths sharing the same rules
+fn migrate_to_v4(config: &mut Map<String, Value>) {
+    if let Some(Value::Array(directories)) = config.get_mut("search_directories") {
+        for directory in directories.iter_mut() {
+            if let Value::String(path) = directory {
+                *directory =
+                    serde_json::to_value(SearchDirectory::new(path)).unwrap_or(Value::Null);
+            }
+        }
+    }
+}
+
+// version 4 files had no mode prefixes
+fn migrate_to_v5(config: &mut Map<String, Value>) {
+    config.entry("mode_prefixes").or_insert_with(|| {
+        serde_json::to_value(get_default_mode_prefixes()).unwrap_or(Value::Array(vec![]))
+    });
+}
+
+// version 5 files had no provider settings
+fn migrate_to_v6(config: &mut Map<String, Value>) {
+    config.entry("providers").or_insert_with(|| {
+        serde_json::to_value(get_default_providers()).unwrap_or(Value::Object(Map::new()))
+    });
+}
+
+// version 6 files had no apps provider and no result limits. The calculator only
+// answered in its own mode back then, so it's moved to the top unless its priority
+// was changed.
+fn migrate_to_v7(config: &mut Map<String, Value>) {
+    let providers = match config.get_mut("providers").and_then(Value::as_object_mut) {
+        Some(providers) => providers,
+        None => return,
+    };
+    for (id, default) in get_default_providers() {
+        match providers.get_mut(&id).and_then(Value::as_object_mut) {
+            Some(provider) => {
+                provider
+                    .entry("max_results")
+                    .or_insert_with(|| Value::from(default.max_results));
+                if id == "calculator" && provider.get("priority") == Some(&Value::from(0)) {
+                    provider.insert("priority".to_string(), Value::from(default.priority));
+                }
+            }
+            None => {
+                providers.insert(id, serde_json::to_value(default).unwrap_or_default());
+            }
+        }
+    }
+}
+
+// version 7 files had no calculator settings
+fn migrate_to_v8(config: &mut Map<String, Value>) {
+    config.entry("calculator").or_insert_with(|| {
+        serde_json::to_value(CalculatorConfig::default()).unwrap_or(Value::Object(Map::new()))
+    });
+}
+
+// version 8 files had no calculator history provider
+fn migrate_to_v9(config: &mut Map<String, Value>) {
+    let default = get_default_providers().remove("calculator_history");
+    if let (Some(providers), Some(default)) = (
+        config.get_mut("providers").and_then(Value::as_object_mut),
+        default,
+    ) {
+        providers
+            .entry("calculator_history")
+            .or_insert_with(|| serde_json::to_value(default).unwrap_or_default());
+    }
+}
+
+// keeps a copy of config.json next to it before it gets replaced
+fn backup_config(filepath: &Path, label: &str) -> Option<PathBuf> {
+    let backup_path = filepath.with_file_name(format!(
+        "config.{}-{}.json.bak",
+        label,
+        Local::now().format("%Y%m%d-%H%M%S")
+    ));
+    match fs::copy(filepath, &backup_path) {
+        Ok(_) => Some(backup_path),
+        Err(e) => {
+            eprintln!("Error backing up the config file: {}", e);
+            None
+        }
+    }
+}
+
+// folders that are huge and never searched for
+fn get_default_exclusions() -> Vec<String> {
     #[cfg(target_os = "macos")]
     {
         vec![
-            format!("{}/Desktop", home_path),
-            format!("{}/Downloads", home_path),
-            format!("{}/Documents", home_path),
-            "/System/Applications".to_string(),
-            "/Applications".to_string(),
+            "Native Instruments".to_string(),
+            "Adobe Creative Cloud".to_string(),
+        ]
+    }
+    #[cfg(not(target_os = "macos"))]
+    {
+        vec![]
+    }
+}
+
+fn get_default_search_directories() -> Vec<SearchDirectory> {
+    #[cfg(target_os = "macos")]
+    {
+        vec![
+            SearchDirectory::new("~/Desktop"),
+            SearchDirectory::new("~/Downloads"),
+            SearchDirectory::new("~/Documents"),
+            SearchDirectory::new("/System/Applications"),
+            SearchDirectory::new("/Applications"),
         ]
     }
     #[cfg(target_os = "windows")]
     {
         vec![
-            format!("{}\\Desktop", home_path),
-            format!("{}\\Downloads", home_path),
-            "C:\\Program Files",
-            "C:\\Program Files (x86)",
+            SearchDirectory::new("~\\Desktop"),
+            SearchDirectory::new("~\\Downloads"),
+            // only the executables, program folders are mostly libraries and resources
+            SearchDirectory {
+                file_types: vec![FileType::Application],
+                ..SearchDirectory::new("C:\\Program Files")
+            },
+            SearchDirectory {
+                file_types: vec![FileType::Application],
+                ..SearchDirectory::new("C:\\Program Files (x86)")
+            },
         ]
     }
 
     #[cfg(target_os = "linux")]
     {
         vec![
-            "/usr/share/applications",
-            "/usr/local/share/applications",
-            "/usr/bin",
-            "/usr/sbin",
+            SearchDirectory::new("/usr/share/applications"),
+            SearchDirectory::new("/usr/local/share/applications"),
+            SearchDirectory::new("/usr/bin"),
+            SearchDirectory::new("/usr/sbin"),
         ]
     }
 }
 
-impl AppConfig {
-    pub fn new() -> Self {
+fn get_default_mode_prefixes() -> Vec<ModePrefix> {
+    vec![
+        ModePrefix::new(">", QueryMode::Scripts),
+        ModePrefix::new("?", QueryMode::Chat),
+        ModePrefix::new("h ", QueryMode::BrowserHistory),
+        ModePrefix::new("=", QueryMode::Calculator),
+    ]
+}
+
+// open tabs beat reopening a url and bookmarks are deliberate, so both rank
+// above plain history
+fn get_default_providers() -> BTreeMap<String, ProviderSettings> {
+    BTreeMap::from([
+        ("calculator".to_string(), ProviderSettings::new(50, 500, 1)),
+        (
+            "calculator_history".to_string(),
+            ProviderSettings::new(45, 500, 20),
+        ),
+        ("apps".to_string(), ProviderSettings::new(40, 1000, 5)),
+        ("open_tabs".to_string(), ProviderSettings::new(30, 500, 10)),
+        ("bookmarks".to_string(), ProviderSettings::new(20, 500, 10)),
+        (
+            "browser_history".to_string(),
+            ProviderSettings::new(10, 1000, 20),
+        ),
+        ("files".to_string(), ProviderSettings::new(0, 1000, 50)),
+        ("scripts".to_string(), ProviderSettings::new(0, 1000, 5)),
+    ])
+}
+
+impl Default for AppConfig {
+    fn default() -> Self {
         Self {
+            version: CONFIG_VERSION,
             launch_shortcut: "Control+Space".to_string(),
             search_directories: get_default_search_directories(),
+            browser_history: BrowserHistoryConfig::default(),
+            keybindings: vec![],
+            mode_prefixes: get_default_mode_prefixes(),
+            providers: get_default_providers(),
+            calculator: CalculatorConfig::default(),
         }
-        .read()
+    }
+}
+
+impl AppConfig {
+    //* Reads config.json for code running in the background. Unlike `load`, it never writes or backs up the file, and it's None while the file can't be used, e.g. while it's half saved. */
+    pub fn read() -> Option<Self> {
+        let contents = fs::read_to_string(config_filepath()?).ok()?;
+        Self::parse(&contents).ok().map(|(config, _)| config)
     }
 
-    pub fn get_search_directories(&self) -> Option<Vec<String>> {
+    //* The search directories with ~ expanded to the home directory */
+    pub fn get_search_directories(&self) -> Option<Vec<SearchDirectory>> {
         home_dir().and_then(|path| {
             path.to_str().map(|path| {
                 self.search_directories
                     .iter()
-                    .map(|dir| {
-                        if dir.starts_with("~") {
-                            dir.replace("~", path)
+                    .map(|dir| SearchDirectory {
+                        path: if dir.path.starts_with("~") {
+                            dir.path.replace("~", path)
                         } else {
-                            dir.to_string()
-                        }
+                            dir.path.to_string()
+                        },
+                        ..dir.clone()
                     })
                     .collect()
             })
         })
     }
 
-    pub fn read(&self) -> Self {
-        config_filepath()
-            .and_then(|filepath| match File::open(filepath) {
-                Ok(file) => Some(file),
-                Err(e) => {
-                    eprintln!("Error opening config file: {}", e);
-                    self.write();
-                    return None;
+    //* Reads config.json, migrating it from older versions. Files that can't be used are backed up and replaced with the defaults. */
+    pub fn load() -> SettingsState {
+        let filepath = match config_filepath() {
+            Some(filepath) => filepath,
+            None => {
+                return SettingsState {
+                    config: Self::default(),
+                    errors: vec![ConfigError::new(
+                        "",
+                        "Unable to locate the config directory, the defaults are in use"
+                            .to_string(),
+                    )],
                 }
-            })
-            .and_then(|mut file| {
-                let mut contents = String::new();
-                if file.read_to_string(&mut contents).is_ok() {
-                    Some(contents)
-                } else {
-                    None
+            }
+        };
+
+        let contents = match fs::read_to_string(&filepath) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                return SettingsState {
+                    config: Self::default().write(),
+                    errors: vec![],
                 }
-            })
-            .and_then(|fcontent| {
-                if let Ok(config) = self.from_json(fcontent) {
-                    Some(config)
-                } else {
-                    None
+            }
+            Err(e) => {
+                return SettingsState {
+                    config: Self::default(),
+                    errors: vec![ConfigError::new(
+                        "",
+                        format!("Error reading config.json, the defaults are in use: {}", e),
+                    )],
+                }
+            }
+        };
+
+        match Self::parse(&contents) {
+            Ok((config, migrated_from)) => Self::migrated(&filepath, config, migrated_from),
+            // never overwrite a file a newer version of the app will still understand
+            Err(e @ ConfigParseError::UnsupportedVersion(_)) => SettingsState {
+                config: Self::default(),
+                errors: vec![ConfigError::new(
+                    "version",
+                    format!("{}, the defaults are in use", e),
+                )],
+            },
+            Err(e) => {
+                // the user's file is only replaced once a copy of it is safe
+                let (config, message) = match backup_config(&filepath, "invalid") {
+                    Some(backup_path) => (
+                        Self::default().write(),
+                        format!(
+                            "{}. It was backed up to {} and replaced with the defaults.",
+                            e,
+                            backup_path.to_string_lossy()
+                        ),
+                    ),
+                    None => (Self::default(), format!("{}. The defaults are in use.", e)),
+                };
+                SettingsState {
+                    config,
+                    errors: vec![ConfigError::new("", message)],
                 }
+            }
+        }
+    }
+
+    //* Reads config.json again while the app is running. Unlike `load`, a file that can't be parsed is left alone, since it's most likely still being edited. */
+    pub fn reload() -> Result<SettingsState, ConfigError> {
+        let filepath = config_filepath().ok_or_else(|| {
+            ConfigError::new("", "Unable to locate the config directory".to_string())
+        })?;
+        let contents = fs::read_to_string(&filepath)
+            .map_err(|e| ConfigError::new("", format!("Error reading config.json: {}", e)))?;
+        Self::parse(&contents)
+            .map(|(config, migrated_from)| Self::migrated(&filepath, config, migrated_from))
+            .map_err(|e| {
+                let field = match e {
+                    ConfigParseError::UnsupportedVersion(_) | ConfigParseError::InvalidVersion => {
+                        "version"
+                    }
+                    _ => "",
+                };
+                ConfigError::new(field, e.to_string())
             })
-            .unwrap_or(self.clone())
+    }
+
+    // writes configs migrated from an older version back, after backing up the original
+    fn migrated(filepath: &Path, config: Self, migrated_from: Option<u32>) -> SettingsState {
+        let config = match migrated_from {
+            Some(version) => {
+                println!("Migrating config.json from version {}", version);
+                backup_config(filepath, &format!("v{}", version));
+                config.write()
+            }
+            None => config,
+        };
+        let errors = config.validate();
+        SettingsState { config, errors }
+    }
+
+    // returns the config along with the version it was migrated from, if it was
+    fn parse(contents: &str) -> Result<(Self, Option<u32>), ConfigParseError> {
+        let mut value: Value = serde_json::from_str(contents)?;
+        let object = value.as_object_mut().ok_or(ConfigParseError::NotAnObject)?;
+        // files written before the config was versioned
+        let version = match object.get("version") {
+            None => 1,
+            Some(version) => version.as_u64().ok_or(ConfigParseError::InvalidVersion)?,
+        };
+        if version > CONFIG_VERSION as u64 {
+            return Err(ConfigParseError::UnsupportedVersion(version));
+        }
+
+        for (_, migrate) in MIGRATIONS
+            .iter()
+            .filter(|(target, _)| *target as u64 > version)
+        {
+            migrate(object);
+        }
+        object.insert("version".to_string(), Value::from(CONFIG_VERSION));
+
+        let config = serde_json::from_value(value)?;
+        Ok((
+            config,
+            (version < CONFIG_VERSION as u64).then_some(version as u32),
+        ))
+    }
+
+    pub fn validate(&self) -> Vec<ConfigError> {
+        let mut errors = vec![];
+        let launch_shortcut = parse_shortcut(&self.launch_shortcut)
+            .map_err(|e| errors.push(ConfigError::new("launch_shortcut", e.to_string())))
+            .ok();
+        for (i, dir) in self.search_directories.iter().enumerate() {
+            if !dir.path.starts_with('~') && !Path::new(&dir.path).is_absolute() {
+                errors.push(ConfigError::new(
+                    &format!("search_directories[{}].path", i),
+                    format!("'{}' must be an absolute path or start with ~", dir.path),
+                ));
+            }
+            for (field, patterns) in [("include", &dir.include), ("exclude", &dir.exclude)] {
+                for (j, pattern) in patterns.iter().enumerate() {
+                    if let Err(e) = Glob::new(pattern) {
+                        errors.push(ConfigError::new(
+                            &format!("search_directories[{}].{}[{}]", i, field, j),
+                            e.to_string(),
+                        ));
+                    }
+                }
+            }
+        }
+
+        let history = &self.browser_history;
+        if history.retention_days == 0 {
+            errors.push(ConfigError::new(
+                "browser_history.retention_days",
+                "History has to be kept for at least one day".to_string(),
+            ));
+        }
+        for (field, patterns) in [
+            ("allowed_domains", &history.allowed_domains),
+            ("denied_domains", &history.denied_domains),
+        ] {
+            for (i, pattern) in patterns.iter().enumerate() {
+                if let Err(e) = DomainPattern::parse(pattern) {
+                    errors.push(ConfigError::new(
+                        &format!("browser_history.{}[{}]", field, i),
+                        e.to_string(),
+                    ));
+                }
+            }
+        }
+        errors.extend(self.validate_keybindings(launch_shortcut));
+        for (i, mode_prefix) in self.mode_prefixes.iter().enumerate() {
+            let field = format!("mode_prefixes[{}].prefix", i);
+            if mode_prefix.prefix.trim().is_empty() {
+                errors.push(ConfigError::new(
+                    &field,
+                    "A prefix needs at least one character besides spaces".to_string(),
+                ));
+            } else if self.mode_prefixes[..i]
+                .iter()
+                .any(|other| other.prefix == mode_prefix.prefix)
+            {
+                errors.push(ConfigError::new(
+                    &field,
+                    format!(
+                        "'{}' is already the prefix of another mode",
+                        mode_prefix.prefix
+                    ),
+                ));
+            }
+        }
+        let known_providers = get_default_providers();
+        for (id, provider) in self.providers.iter() {
+            // plugins are only known once they're running
+            if !known_providers.contains_key(id) && !id.starts_with(PLUGIN_PROVIDER_PREFIX) {
+                errors.push(ConfigError::new(
+                    &format!("providers.{}", id),
+                    format!(
+                        "'{}' is not a provider, use one of {} or {}<name of the plugin>",
+                        id,
+                        known_providers
+                            .keys()
+                            .map(String::as_str)
+                            .collect::<Vec<_>>()
+                            .join(", "),
+                        PLUGIN_PROVIDER_PREFIX
+                    ),
+                ));
+            } else if provider.timeout_ms == 0 {
+                errors.push(ConfigError::new(
+                    &format!("providers.{}.timeout_ms", id),
+                    "A provider needs at least 1ms to answer".to_string(),
+                ));
+            } else if provider.max_results == 0 {
+                errors.push(ConfigError::new(
+                    &format!("providers.{}.max_results", id),
+                    "Disable the provider instead of limiting it to 0 results".to_string(),
+                ));
+            }
+        }
+        match Url::parse(&self.calculator.exchange_rates_url) {
+            Ok(url) if url.scheme() == "http" || url.scheme() == "https" => {}
+            Ok(url) => errors.push(ConfigError::new(
+                "calculator.exchange_rates_url",
+                format!("'{}' is not an http or https url", url),
+            )),
+            Err(e) => errors.push(ConfigError::new(
+                "calculator.exchange_rates_url",
+                e.to_string(),
+            )),
+        }
+        errors
+    }
+
+    fn validate_keybindings(&self, launch_shortcut: Option<Shortcut>) -> Vec<ConfigError> {
+        let mut errors = vec![];
+        let mut bound: Vec<Shortcut> = launch_shortcut.into_iter().collect();
+        for (i, keybinding) in self.keybindings.iter().enumerate() {
+            match parse_shortcut(&keybinding.shortcut) {
+                Ok(shortcut) if bound.iter().any(|other| same_keys(other, &shortcut)) => errors
+                    .push(ConfigError::new(
+                        &format!("keybindings[{}].shortcut", i),
+                        format!(
+                            "'{}' is already bound to the launch shortcut or another keybinding",
+                            keybinding.shortcut
+                        ),
+                    )),
+                Ok(shortcut) => bound.push(shortcut),
+                Err(e) => errors.push(ConfigError::new(
+                    &format!("keybindings[{}].shortcut", i),
+                    e.to_string(),
+                )),
+            }
+
+            match &keybinding.action {
+                KeybindingAction::RunScript { script_name }
+                    if script_name.trim().is_empty()
+                        || script_name.contains(['/', '\\'])
+                        || script_name.starts_with('.') =>
+                {
+                    errors.push(ConfigError::new(
+                        &format!("keybindings[{}].action.script_name", i),
+                        format!(
+                            "'{}' must be the name of a file in the scripts directory",
+                            script_name
+                        ),
+                    ))
+                }
+                KeybindingAction::OpenFile { path }
+                    if !path.starts_with('~') && !Path::new(path).is_absolute() =>
+                {
+                    errors.push(ConfigError::new(
+                        &format!("keybindings[{}].action.path", i),
+                        format!("'{}' must be an absolute path or start with ~", path),
+                    ))
+                }
+                _ => {}
+            }
+        }
+        errors
     }
 
     pub fn write(&self) -> Self {
+        let mut config = self.clone();
+        config.version = CONFIG_VERSION;
         config_filepath()
-            .and_then(|filepath| match File::create(filepath) {
-                Ok(file) => Some(file),
-                Err(e) => {
-                    eprintln!("Error creating the config file: {}", e);
-                    return None;
-                }
-            })
-            .and_then(|mut file| match self.to_json() {
-                Ok(file_content) => {
-                    if file.write_all(file_content.as_bytes()).is_ok() {
-                        Some(self.clone())
-                    } else {
+            .and_then(|filepath| match config.to_json() {
+                Ok(file_content) => match fs::write(filepath, file_content) {
+                    Ok(_) => Some(config.clone()),
+                    Err(e) => {
+                        eprintln!("Error writing the config file: {}", e);
                         None
                     }
-                }
+                },
                 Err(e) => {
                     eprintln!("Error parsing config into a string {}", e);
-                    return None;
+                    None
                 }
             })
-            .unwrap_or(self.clone())
-    }
-
-    pub fn from_json(&self, f: String) -> Result<Self, serde_json::Error> {
-        serde_json::from_str(&f)
+            .unwrap_or(config)
     }
 
     pub fn to_json(&self) -> serde_json::Result<String> {
         serde_json::to_string_pretty(self)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(name: &str) -> String {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures")
+            .join(name);
+        fs::read_to_string(path).unwrap()
+    }
+
+    fn backups(dir: &Path, label: &str) -> Vec<PathBuf> {
+        fs::read_dir(dir)
+            .unwrap()
+            .filter_map(Result::ok)
+            .map(|entry| entry.path())
+            .filter(|path| {
+                path.file_name()
+                    .map(|name| name.to_string_lossy())
+                    .map_or(false, |name| {
+                        name.starts_with(&format!("config.{}-", label)) && name.ends_with(".bak")
+                    })
+            })
+            .collect()
+    }
+
+    #[test]
+    fn migrates_a_v1_file_to_the_current_version() {
+        let (config, migrated_from) = AppConfig::parse(&fixture("config_v1.json")).unwrap();
+        assert_eq!(migrated_from, Some(1));
+        assert_eq!(
+            config,
+            AppConfig {
+                launch_shortcut: "Alt+Space".to_string(),
+                search_directories: vec![
+                    SearchDirectory::new("~/Code"),
+                    SearchDirectory::new("/opt/projects"),
+                ],
+                ..AppConfig::default()
+            }
+        );
+        assert_eq!(config.version, CONFIG_VERSION);
+        assert!(config.validate().is_empty(), "{:?}", config.validate());
+    }
+
+    #[test]
+    fn migrates_a_v6_file_keeping_its_settings() {
+        let (config, migrated_from) = AppConfig::parse(&fixture("config_v6.json")).unwrap();
+        assert_eq!(migrated_from, Some(6));
+        assert_eq!(config.version, CONFIG_VERSION);
+        assert_eq!(config.launch_shortcut, "Control+Shift+Space");
+        assert_eq!(config.search_directories[0].max_depth, Some(3));
+        assert_eq!(config.search_directories[0].file_types, vec![FileType::Pdf]);
+        assert_eq!(
+            config.browser_history.disabled_browsers,
+            vec![Browser::Safari]
+        );
+        assert_eq!(config.browser_history.retention_days, 30);
+        assert_eq!(config.keybindings.len(), 1);
+        assert_eq!(
+            config.mode_prefixes,
+            vec![ModePrefix::new("!", QueryMode::Scripts)]
+        );
+        assert_eq!(config.calculator, CalculatorConfig::default());
+
+        let defaults = get_default_providers();
+        // the calculator's old default priority moves it to the top
+        assert_eq!(config.providers["calculator"], defaults["calculator"]);
+        assert_eq!(
+            config.providers["files"],
+            ProviderSettings {
+                priority: 5,
+                timeout_ms: 2000,
+                ..defaults["files"].clone()
+            }
+        );
+        assert!(!config.providers["browser_history"].enabled);
+        for id in [
+            "apps",
+            "open_tabs",
+            "bookmarks",
+            "scripts",
+            "calculator_history",
+        ] {
+            assert_eq!(config.providers[id], defaults[id], "{}", id);
+        }
+        assert!(config.validate().is_empty(), "{:?}", config.validate());
+    }
+
+    #[test]
+    fn current_files_are_not_migrated() {
+        let json = AppConfig::default().to_json().unwrap();
+        let (config, migrated_from) = AppConfig::parse(&json).unwrap();
+        assert_eq!(migrated_from, None);
+        assert_eq!(config, AppConfig::default());
+    }
+
+    #[test]
+    fn rejects_files_it_cant_use() {
+        assert!(matches!(
+            AppConfig::parse("{ not json"),
+            Err(ConfigParseError::Invalid(_))
+        ));
+        assert!(matches!(
+            AppConfig::parse("[]"),
+            Err(ConfigParseError::NotAnObject)
+        ));
+        assert!(matches!(
+            AppConfig::parse(r#"{"version": "9"}"#),
+            Err(ConfigParseError::InvalidVersion)
+        ));
+        assert!(matches!(
+            AppConfig::parse(r#"{"version": 99}"#),
+            Err(ConfigParseError::UnsupportedVersion(99))
+        ));
+    }
+
+    // the only test touching the config directory, so nothing else races the variable
+    #[test]
+    fn load_backs_up_files_before_replacing_them() {
+        let dir = std::env::temp_dir().join(format!("swordfish-settings-{}", std::process::id()));
+        fs::remove_dir_all(&dir).ok();
+        fs::create_dir_all(&dir).unwrap();
+        std::env::set_var("XDG_CONFIG_HOME", &dir);
+        let filepath = config_filepath().unwrap();
+        let config_dir = filepath.parent().unwrap();
+
+        let v1 = fixture("config_v1.json");
+        fs::write(&filepath, &v1).unwrap();
+        let state = AppConfig::load();
+        assert!(state.errors.is_empty(), "{:?}", state.errors);
+        assert_eq!(state.config.launch_shortcut, "Alt+Space");
+        let migrated_backups = backups(config_dir, "v1");
+        assert_eq!(migrated_backups.len(), 1);
+        assert_eq!(fs::read_to_string(&migrated_backups[0]).unwrap(), v1);
+        let (written, migrated_from) =
+            AppConfig::parse(&fs::read_to_string(&filepath).unwrap()).unwrap();
+        assert_eq!(migrated_from, None);
+        assert_eq!(written, state.config);
+
+        fs::write(&filepath, "{ not json").unwrap();
+        let state = AppConfig::load();
+        assert_eq!(state.config, AppConfig::default());
+        assert_eq!(state.errors.len(), 1);
+        let invalid_backups = backups(config_dir, "invalid");
+        assert_eq!(invalid_backups.len(), 1);
+        assert!(state.errors[0]
+            .message
+            .contains(&*invalid_backups[0].to_string_lossy()));
+        assert_eq!(
+            fs::read_to_string(&invalid_backups[0]).unwrap(),
+            "{ not json"
+        );
+        assert_eq!(
+            AppConfig::parse(&fs::read_to_string(&filepath).unwrap())
+                .unwrap()
+                .0,
+            AppConfig::default()
+        );
+
+        fs::remove_dir_all(&dir).ok();
+    }
+}
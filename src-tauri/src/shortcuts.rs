@@ -0,0 +1,217 @@
+use std::{fs, str::FromStr};
+
+use serde_variant::to_variant_name;
+use swordfish_types::SFEvent;
+use swordfish_utilities::get_scripts_path;
+use tauri::{AppHandle, Emitter, Manager};
+use tauri_plugin_global_shortcut::{GlobalShortcutExt, Shortcut};
+use tauri_plugin_shell::ShellExt;
+use thiserror::Error;
+
+use crate::app_state::AppState;
+use crate::settings::{AppConfig, ConfigError, KeybindingAction};
+use crate::windows::{show_main_window, toggle_main_window};
+
+#[derive(Error, Debug)]
+pub enum ShortcutError {
+    #[error("A shortcut can't be empty")]
+    Empty,
+    #[error("'{0}' is not a valid shortcut, use something like \"Control+Shift+Space\": {1}")]
+    Invalid(String, String),
+    #[error("'{0}' needs at least one modifier (Control, Alt, Shift or Super)")]
+    MissingModifier(String),
+    #[error("'{0}' is reserved for {1}")]
+    Reserved(String, &'static str),
+    #[error("'{0}' couldn't be registered, another application is probably using it: {1}")]
+    Unavailable(String, String),
+}
+
+// shortcuts the OS handles before any application gets to see them
+#[cfg(target_os = "macos")]
+const RESERVED_SHORTCUTS: &[(&str, &str)] = &[
+    ("Super+Space", "Spotlight"),
+    ("Super+Tab", "the application switcher"),
+    ("Super+Q", "quitting applications"),
+    ("Control+Super+Q", "locking the screen"),
+    ("Shift+Super+3", "screenshots"),
+    ("Shift+Super+4", "screenshots"),
+    ("Shift+Super+5", "screenshots"),
+];
+#[cfg(target_os = "windows")]
+const RESERVED_SHORTCUTS: &[(&str, &str)] = &[
+    ("Alt+Tab", "the task switcher"),
+    ("Alt+F4", "closing windows"),
+    ("Super+Tab", "Task View"),
+    ("Super+L", "locking the screen"),
+    ("Super+D", "showing the desktop"),
+    ("Control+Alt+Delete", "the security screen"),
+];
+#[cfg(target_os = "linux")]
+const RESERVED_SHORTCUTS: &[(&str, &str)] = &[
+    ("Alt+Tab", "the window switcher"),
+    ("Alt+F4", "closing windows"),
+    ("Super+L", "locking the screen"),
+    ("Control+Alt+Delete", "logging out"),
+];
+
+pub fn same_keys(a: &Shortcut, b: &Shortcut) -> bool {
+    a.mods == b.mods && a.key == b.key
+}
+
+//* Parses a shortcut like "Control+Shift+Space", rejecting the ones that can't work as a global shortcut */
+pub fn parse_shortcut(shortcut: &str) -> Result<Shortcut, ShortcutError> {
+    let shortcut = shortcut.trim();
+    if shortcut.is_empty() {
+        return Err(ShortcutError::Empty);
+    }
+    let parsed = Shortcut::from_str(shortcut)
+        .map_err(|e| ShortcutError::Invalid(shortcut.to_string(), e.to_string()))?;
+    // a single key would stop working everywhere else
+    if parsed.mods.is_empty() {
+        return Err(ShortcutError::MissingModifier(shortcut.to_string()));
+    }
+    match RESERVED_SHORTCUTS.iter().find(|(reserved, _)| {
+        Shortcut::from_str(reserved).map_or(false, |reserved| same_keys(&reserved, &parsed))
+    }) {
+        Some((_, owner)) => Err(ShortcutError::Reserved(shortcut.to_string(), owner)),
+        None => Ok(parsed),
+    }
+}
+
+// the launch shortcut and every keybinding that parses
+fn config_shortcuts(config: &AppConfig) -> Vec<Shortcut> {
+    std::iter::once(&config.launch_shortcut)
+        .chain(
+            config
+                .keybindings
+                .iter()
+                .map(|keybinding| &keybinding.shortcut),
+        )
+        .filter_map(|shortcut| Shortcut::from_str(shortcut).ok())
+        .collect()
+}
+
+//* Registers the launch shortcut and keybindings of `config` in place of the ones of `previous` */
+pub fn replace_shortcuts(
+    app: &AppHandle,
+    previous: &AppConfig,
+    config: &AppConfig,
+) -> Vec<ConfigError> {
+    let global_shortcut = app.global_shortcut();
+    // unregistering the new ones too makes this safe to repeat
+    for shortcut in config_shortcuts(previous)
+        .into_iter()
+        .chain(config_shortcuts(config))
+    {
+        global_shortcut.unregister(shortcut).ok();
+    }
+
+    let mut errors = vec![];
+    let register = |shortcut: &str| {
+        let parsed = parse_shortcut(shortcut)?;
+        global_shortcut
+            .register(parsed)
+            .map_err(|e| ShortcutError::Unavailable(shortcut.trim().to_string(), e.to_string()))
+    };
+    if let Err(e) = register(&config.launch_shortcut) {
+        errors.push(ConfigError::new("launch_shortcut", e.to_string()));
+        // keeps swordfish reachable, unless a keybinding took over the previous shortcut
+        let previous_taken = Shortcut::from_str(&previous.launch_shortcut).map_or(true, |launch| {
+            config_shortcuts(config)
+                .iter()
+                .any(|shortcut| same_keys(shortcut, &launch))
+        });
+        if !previous_taken {
+            register(&previous.launch_shortcut).ok();
+        }
+    }
+    for (i, keybinding) in config.keybindings.iter().enumerate() {
+        if let Err(e) = register(&keybinding.shortcut) {
+            errors.push(ConfigError::new(
+                &format!("keybindings[{}].shortcut", i),
+                e.to_string(),
+            ));
+        }
+    }
+    errors
+}
+
+//* Registers the configured launch shortcut and keybindings */
+pub fn register_shortcuts(app: &AppHandle) -> Vec<ConfigError> {
+    match app.state::<AppState>().config.lock() {
+        Ok(config) => {
+            let config = config.clone();
+            replace_shortcuts(app, &config, &config)
+        }
+        Err(e) => vec![ConfigError::new("", e.to_string())],
+    }
+}
+
+//* Unregisters the launch shortcut so pressing it reaches the settings window while a new one is recorded */
+pub fn suspend_launch_shortcut(app: &AppHandle) {
+    let launch_shortcut = app
+        .state::<AppState>()
+        .config
+        .lock()
+        .ok()
+        .and_then(|config| Shortcut::from_str(&config.launch_shortcut).ok());
+    if let Some(launch_shortcut) = launch_shortcut {
+        app.global_shortcut().unregister(launch_shortcut).ok();
+    }
+}
+
+//* Runs whatever the pressed shortcut is bound to */
+pub fn handle_shortcut(app: &AppHandle, shortcut: &Shortcut) {
+    let matches =
+        |bound: &str| Shortcut::from_str(bound).map_or(false, |bound| same_keys(&bound, shortcut));
+    let (is_launch_shortcut, action) = match app.state::<AppState>().config.lock() {
+        Ok(config) => (
+            matches(&config.launch_shortcut),
+            config
+                .keybindings
+                .iter()
+                .find(|keybinding| matches(&keybinding.shortcut))
+                .map(|keybinding| keybinding.action.clone()),
+        ),
+        Err(_) => return,
+    };
+
+    if is_launch_shortcut {
+        toggle_main_window(app.to_owned());
+    } else if let Some(action) = action {
+        run_action(app, action);
+    }
+}
+
+fn run_action(app: &AppHandle, action: KeybindingAction) {
+    match action {
+        KeybindingAction::OpenQueryMode { mode } => {
+            show_main_window(app.to_owned());
+            app.emit(to_variant_name(&SFEvent::SetQueryMode).unwrap(), mode)
+                .ok();
+        }
+        KeybindingAction::RunScript { script_name } => {
+            let script = get_scripts_path()
+                .map(|dir| dir.join(&script_name))
+                .and_then(|path| fs::read_to_string(path).ok());
+            match script {
+                Some(script) => {
+                    app.emit(to_variant_name(&SFEvent::RunScript).unwrap(), script)
+                        .ok();
+                }
+                None => eprintln!("Unable to read the script '{}'", script_name),
+            }
+        }
+        KeybindingAction::OpenFile { path } => {
+            let path = match dirs::home_dir() {
+                Some(home) if path.starts_with('~') => {
+                    path.replacen('~', &home.to_string_lossy(), 1)
+                }
+                _ => path,
+            };
+            if let Err(e) = app.shell().open(&path, None) {
+                eprintln!("Unable to open '{}': {}", path, e);
+            }
+        }
+    }
+}
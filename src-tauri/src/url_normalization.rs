@@ -0,0 +1,245 @@
+use url::Url;
+
+//* Query parameters that only identify where a click came from, never which page it is */
+const TRACKING_PARAMS: &[&str] = &[
+    "fbclid", "gclid", "gclsrc", "dclid", "gbraid", "wbraid", "msclkid", "yclid", "twclid",
+    "igshid", "mc_cid", "mc_eid", "_hsenc", "_hsmi", "mkt_tok", "ref_src", "ref_url", "si",
+];
+const TRACKING_PARAM_PREFIXES: &[&str] = &["utm_", "pk_", "mtm_"];
+
+//* The query parameters that identify a page on a domain, every other parameter is dropped */
+struct QueryRule {
+    /* matches the domain and its subdomains */
+    domain: &'static str,
+    significant_params: &'static [&'static str],
+    keep_fragment: bool,
+}
+
+const QUERY_RULES: &[QueryRule] = &[
+    QueryRule {
+        domain: "youtube.com",
+        significant_params: &["v", "list", "search_query"],
+        keep_fragment: false,
+    },
+    QueryRule {
+        domain: "youtu.be",
+        significant_params: &[],
+        keep_fragment: false,
+    },
+    QueryRule {
+        // the fragment of a doc or sheet selects the tab or heading
+        domain: "docs.google.com",
+        significant_params: &["gid", "tab"],
+        keep_fragment: true,
+    },
+    QueryRule {
+        domain: "google.com",
+        significant_params: &["q", "tbm"],
+        keep_fragment: false,
+    },
+    QueryRule {
+        domain: "bing.com",
+        significant_params: &["q"],
+        keep_fragment: false,
+    },
+    QueryRule {
+        domain: "duckduckgo.com",
+        significant_params: &["q", "ia"],
+        keep_fragment: false,
+    },
+    QueryRule {
+        domain: "github.com",
+        significant_params: &["q", "tab", "type"],
+        keep_fragment: false,
+    },
+    QueryRule {
+        domain: "news.ycombinator.com",
+        significant_params: &["id", "p"],
+        keep_fragment: false,
+    },
+];
+
+fn query_rule(host: &str) -> Option<&'static QueryRule> {
+    QUERY_RULES.iter().find(|rule| {
+        host == rule.domain
+            || host
+                .strip_suffix(rule.domain)
+                .map_or(false, |subdomain| subdomain.ends_with('.'))
+    })
+}
+
+fn is_tracking_param(name: &str) -> bool {
+    let name = name.to_lowercase();
+    TRACKING_PARAMS.contains(&name.as_str())
+        || TRACKING_PARAM_PREFIXES
+            .iter()
+            .any(|prefix| name.starts_with(prefix))
+}
+
+// fragments starting with "/" or "!" are routes of single page apps, anything
+// else is an anchor within the same page
+fn is_route_fragment(fragment: &str) -> bool {
+    fragment.starts_with('/') || fragment.starts_with('!')
+}
+
+//* The form two urls are compared in: tracking and insignificant parameters removed, the rest sorted */
+pub fn normalize_url(url: &str) -> String {
+    let mut parsed = match Url::parse(url) {
+        Ok(parsed) => parsed,
+        Err(_) => return url.to_string(),
+    };
+    let rule = parsed
+        .host_str()
+        .map(str::to_lowercase)
+        .and_then(|host| query_rule(&host));
+
+    let mut params: Vec<(String, String)> = parsed
+        .query_pairs()
+        .into_owned()
+        .filter(|(name, _)| match rule {
+            Some(rule) => rule.significant_params.contains(&name.as_str()),
+            None => !is_tracking_param(name),
+        })
+        .collect();
+    params.sort();
+    if params.is_empty() {
+        parsed.set_query(None);
+    } else {
+        parsed.query_pairs_mut().clear().extend_pairs(params);
+    }
+
+    let keep_fragment = match rule {
+        Some(rule) => rule.keep_fragment,
+        None => parsed.fragment().map_or(false, is_route_fragment),
+    };
+    if !keep_fragment {
+        parsed.set_fragment(None);
+    }
+
+    parsed
+        .as_str()
+        .trim_end_matches(&['/', '#', '?', '&'])
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strips_tracking_params_and_sorts_the_rest() {
+        assert_eq!(
+            normalize_url("https://example.com/p?utm_source=x&b=2&a=1&fbclid=z&PK_campaign=y"),
+            "https://example.com/p?a=1&b=2"
+        );
+        assert_eq!(
+            normalize_url("https://example.com/?utm_medium=x&gclid=1"),
+            "https://example.com"
+        );
+        assert_eq!(
+            normalize_url("https://example.com/p?b=2&a=1"),
+            normalize_url("https://example.com/p?a=1&b=2")
+        );
+    }
+
+    #[test]
+    fn drops_anchors_but_keeps_routes() {
+        assert_eq!(
+            normalize_url("https://example.com/docs#installation"),
+            "https://example.com/docs"
+        );
+        assert_eq!(
+            normalize_url("https://mail.example.com/#/inbox/1"),
+            "https://mail.example.com/#/inbox/1"
+        );
+        assert_eq!(
+            normalize_url("https://app.example.com/#!/settings"),
+            "https://app.example.com/#!/settings"
+        );
+    }
+
+    #[test]
+    fn trims_trailing_slashes_and_separators() {
+        assert_eq!(
+            normalize_url("https://example.com/path/"),
+            "https://example.com/path"
+        );
+        assert_eq!(
+            normalize_url("https://example.com/?"),
+            "https://example.com"
+        );
+        assert_eq!(
+            normalize_url("https://example.com/#"),
+            "https://example.com"
+        );
+        assert_eq!(
+            normalize_url("https://example.com/path/"),
+            normalize_url("https://example.com/path")
+        );
+    }
+
+    #[test]
+    fn drops_default_ports_and_lowercases_hosts() {
+        assert_eq!(
+            normalize_url("https://Example.COM:443/a"),
+            "https://example.com/a"
+        );
+        assert_eq!(
+            normalize_url("http://example.com:80/a"),
+            "http://example.com/a"
+        );
+        assert_eq!(
+            normalize_url("http://localhost:8080/a"),
+            "http://localhost:8080/a"
+        );
+    }
+
+    #[test]
+    fn keeps_only_the_significant_params_of_known_domains() {
+        assert_eq!(
+            normalize_url("https://www.youtube.com/watch?v=abc&feature=share&t=10#comments"),
+            "https://www.youtube.com/watch?v=abc"
+        );
+        assert_ne!(
+            normalize_url("https://www.youtube.com/watch?v=abc"),
+            normalize_url("https://www.youtube.com/watch?v=def")
+        );
+        assert_eq!(
+            normalize_url("https://youtu.be/abc?si=xyz&t=5"),
+            "https://youtu.be/abc"
+        );
+        assert_eq!(
+            normalize_url("https://www.google.com/search?q=rust&sca_esv=1&ei=2"),
+            "https://www.google.com/search?q=rust"
+        );
+        assert_eq!(
+            normalize_url("https://github.com/rust-lang/rust/issues?q=is%3Aopen&page=2"),
+            "https://github.com/rust-lang/rust/issues?q=is%3Aopen"
+        );
+    }
+
+    #[test]
+    fn keeps_the_fragment_where_a_domain_needs_it() {
+        assert_eq!(
+            normalize_url("https://docs.google.com/spreadsheets/d/ID/edit?usp=sharing#gid=5"),
+            "https://docs.google.com/spreadsheets/d/ID/edit#gid=5"
+        );
+    }
+
+    #[test]
+    fn rules_match_subdomains_only_on_a_dot() {
+        assert!(query_rule("www.youtube.com").is_some());
+        assert!(query_rule("youtube.com").is_some());
+        assert!(query_rule("notyoutube.com").is_none());
+        // the tracking params are dropped on a domain without a rule, the rest is kept
+        assert_eq!(
+            normalize_url("https://notyoutube.com/watch?v=abc&feature=share&utm_source=x"),
+            "https://notyoutube.com/watch?feature=share&v=abc"
+        );
+    }
+
+    #[test]
+    fn leaves_unparsable_urls_alone() {
+        assert_eq!(normalize_url("not a url"), "not a url");
+    }
+}
@@ -72,6 +72,75 @@ pub enum FileType {
 }
 
 impl FileType {
+    //* Every variant, in declaration order */
+    pub const ALL: &'static [FileType] = &[
+        FileType::File,
+        FileType::Directory,
+        FileType::Binary,
+        FileType::Typescript,
+        FileType::Javascript,
+        FileType::MarkdownX,
+        FileType::Rust,
+        FileType::Python,
+        FileType::C,
+        FileType::CHeader,
+        FileType::ObjectCode,
+        FileType::Executable,
+        FileType::MakeFile,
+        FileType::Cpp,
+        FileType::Java,
+        FileType::Go,
+        FileType::Text,
+        FileType::Markdown,
+        FileType::Json,
+        FileType::Xml,
+        FileType::Yaml,
+        FileType::Toml,
+        FileType::Sql,
+        FileType::Html,
+        FileType::Css,
+        FileType::Sass,
+        FileType::Application,
+        FileType::Image,
+        FileType::Icon,
+        FileType::Font,
+        FileType::EBook,
+        FileType::AffinityDesign,
+        FileType::SchemeSource,
+        FileType::VectorImage,
+        FileType::Video,
+        FileType::Audio,
+        FileType::Archive,
+        FileType::Pdf,
+        FileType::Word,
+        FileType::Excel,
+        FileType::Powerpoint,
+        FileType::Stereolithography,
+        FileType::DiskImage,
+        FileType::ThreeDimensionalManufacturingFormat,
+        FileType::GCode,
+        FileType::PKG,
+        FileType::CSV,
+        FileType::Prisma,
+        FileType::BitwigProject,
+        FileType::VirtualStudioTechnology,
+        FileType::ApplePropertyList,
+        FileType::EmacsLisp,
+        FileType::Lua,
+        FileType::Swift,
+        FileType::BrewFile,
+        FileType::AppleScript,
+        FileType::Fish,
+        FileType::Zshell,
+        FileType::ShellScript,
+        FileType::CronSchedule,
+        FileType::FLStudioProject,
+        FileType::PixelmatorProject,
+        FileType::AdobePhotoshopProject,
+        FileType::AdobeIllustratorProject,
+        FileType::SketchDesignProject,
+    ];
+
     pub fn from_extension(file_extension: Option<&str>) -> FileType {
         match file_extension.unwrap_or("") {
             "ts" | "tsx" => FileType::Typescript,
@@ -0,0 +1,112 @@
+use serde::{Deserialize, Serialize};
+use std::path::{Component, Path};
+use ts_rs::TS;
+
+use crate::{FileInfo, FileType};
+
+//* A filter typed into the search box, like "ext:rs" or "size:>10mb" */
+#[derive(TS, Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[ts(export, export_to = "../../../src/types/")]
+pub struct QueryFilter {
+    /* the filter as it was typed, shown on its chip */
+    pub text: String,
+    pub condition: FilterCondition,
+}
+
+#[derive(TS, Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[ts(export, export_to = "../../../src/types/")]
+#[serde(tag = "type")]
+pub enum FilterCondition {
+    Extension {
+        extensions: Vec<String>,
+    },
+    FileType {
+        #[serde(rename = "fileTypes")]
+        file_types: Vec<FileType>,
+    },
+    Path {
+        path: String,
+    },
+    /* unix timestamps in seconds, after is inclusive and before is exclusive */
+    Modified {
+        #[ts(type = "number | null")]
+        after: Option<i64>,
+        #[ts(type = "number | null")]
+        before: Option<i64>,
+    },
+    Created {
+        #[ts(type = "number | null")]
+        after: Option<i64>,
+        #[ts(type = "number | null")]
+        before: Option<i64>,
+    },
+    /* in bytes, both inclusive */
+    Size {
+        #[ts(type = "number | null")]
+        min: Option<u64>,
+        #[ts(type = "number | null")]
+        max: Option<u64>,
+    },
+    Invalid {
+        message: String,
+    },
+}
+
+fn in_range<T: PartialOrd>(value: Option<T>, after: &Option<T>, before: &Option<T>) -> bool {
+    match value {
+        Some(value) => {
+            after.as_ref().map_or(true, |after| value >= *after)
+                && before.as_ref().map_or(true, |before| value < *before)
+        }
+        None => after.is_none() && before.is_none(),
+    }
+}
+
+fn normalized_components(path: &Path) -> Vec<String> {
+    path.components()
+        .filter_map(|component| match component {
+            Component::Normal(name) => Some(name.to_string_lossy().to_lowercase()),
+            _ => None,
+        })
+        .collect()
+}
+
+// an absolute path has to contain the file, a relative one only has to be part of its path
+fn is_inside(file_path: &str, directory: &str) -> bool {
+    let file_path = Path::new(file_path);
+    let directory = Path::new(directory);
+    if directory.is_absolute() {
+        return file_path.starts_with(directory);
+    }
+    let needle = normalized_components(directory);
+    let parents = file_path
+        .parent()
+        .map(normalized_components)
+        .unwrap_or_default();
+    !needle.is_empty() && parents.windows(needle.len()).any(|window| window == needle)
+}
+
+impl QueryFilter {
+    //* Whether a file passes the filter, invalid filters let everything through */
+    pub fn matches(&self, file: &FileInfo) -> bool {
+        match &self.condition {
+            FilterCondition::Extension { extensions } => {
+                file.extension.as_ref().map_or(false, |extension| {
+                    extensions
+                        .iter()
+                        .any(|wanted| wanted.eq_ignore_ascii_case(extension))
+                })
+            }
+            FilterCondition::FileType { file_types } => file_types.contains(&file.file_type),
+            FilterCondition::Path { path } => is_inside(&file.path, path),
+            FilterCondition::Modified { after, before } => {
+                in_range(file.last_modified, after, before)
+            }
+            FilterCondition::Created { after, before } => in_range(file.created, after, before),
+            FilterCondition::Size { min, max } => {
+                min.map_or(true, |min| file.size >= min) && max.map_or(true, |max| file.size <= max)
+            }
+            FilterCondition::Invalid { .. } => true,
+        }
+    }
+}
@@ -1,10 +1,19 @@
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use std::{ffi::OsStr, fs, path::Path, str::FromStr};
+use std::{
+    ffi::OsStr,
+    fs,
+    path::Path,
+    str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
+};
 use swordfish_utilities::get_cached_app_icon_path;
 use ts_rs::TS;
 
 mod file_type;
-use file_type::FileType;
+mod query_filter;
+pub use file_type::FileType;
+pub use query_filter::{FilterCondition, QueryFilter};
 
 #[derive(TS, Serialize, Deserialize, Debug, Clone)]
 #[ts(
@@ -37,13 +46,14 @@ pub trait DataSource<T> {
     fn query(&self, query: &Query) -> Option<T>;
 }
 
-#[derive(TS, Deserialize, Debug, Serialize, Clone)]
+#[derive(TS, Deserialize, Debug, Serialize, Clone, PartialEq)]
 #[ts(export, export_to = "../../../src/types/")]
 pub enum QueryMode {
     Search,
     BrowserHistory,
     Chat,
     Scripts,
+    Calculator,
 }
 
 #[derive(TS, Deserialize, Debug, Serialize, Clone)]
@@ -58,9 +68,12 @@ pub struct Query {
 pub enum ResultType {
     File,
     BrowserHistory,
+    Bookmark,
+    OpenTab,
     Script,
     Action,
     Calculator,
+    Plugin,
 }
 
 #[derive(TS, Deserialize, Debug, Serialize, Clone)]
@@ -80,6 +93,23 @@ pub struct ResultItem {
 #[ts(export, export_to = "../../../src/types/")]
 pub struct QueryResult {
     pub results: Vec<ResultItem>,
+    /* filters parsed out of the search string, empty in modes that don't support them */
+    #[serde(default)]
+    pub filters: Vec<QueryFilter>,
+    /* the mode that answered, which a mode prefix can make differ from the one queried */
+    pub mode: QueryMode,
+    /* how the results are grouped, in the order they're listed */
+    #[serde(default)]
+    pub sections: Vec<ResultSection>,
+}
+
+//* A run of `count` results in `QueryResult.results` starting at `start`, e.g. the files */
+#[derive(TS, Deserialize, Debug, Serialize, Clone, PartialEq)]
+#[ts(export, export_to = "../../../src/types/")]
+pub struct ResultSection {
+    pub title: String,
+    pub start: usize,
+    pub count: usize,
 }
 
 impl ResultItem {
@@ -106,7 +136,8 @@ impl ResultItem {
                 extension: file_info.extension,
                 file_type: file_info.file_type,
                 size: file_info.size.to_string(),
-                last_modified: file_info.last_modified.map(|i| i.to_string()),
+                last_modified: file_info.last_modified.and_then(to_iso_8601),
+                created: file_info.created.and_then(to_iso_8601),
                 content: "".to_string(),
                 parsed_content: Some("".to_string()),
             }),
@@ -125,8 +156,10 @@ pub enum ResultDetails {
         #[serde(rename = "fileType")]
         file_type: FileType,
         size: String,
+        /* ISO-8601 */
         #[serde(rename = "lastModified")]
         last_modified: Option<String>,
+        created: Option<String>,
         content: String,
         #[serde(rename = "parsedContent")]
         parsed_content: Option<String>,
@@ -137,6 +170,19 @@ pub enum ResultDetails {
         image_url: String,
         heading: String,
         subheading: String,
+        /* browser and profile the entry came from, e.g. "Chrome — Work" */
+        source: String,
+    },
+    Bookmark {
+        url: String,
+        heading: String,
+        folder: String,
+        source: String,
+    },
+    OpenTab {
+        url: String,
+        heading: String,
+        source: String,
     },
     Script {
         path: String,
@@ -150,6 +196,13 @@ pub enum ResultDetails {
     Calculator {
         #[serde(rename = "parsedContent")]
         parsed_content: String,
+        /* what was calculated, it's added to the history when the result is picked */
+        expression: String,
+    },
+    Plugin {
+        /* id of the plugin the result came from, it's asked to run the result's action */
+        plugin: String,
+        description: Option<String>,
     },
 }
 
@@ -165,6 +218,8 @@ pub enum SFEvent {
     QueryResult,
     RunScript,
     ScriptResult,
+    SettingsChanged,
+    SetQueryMode,
 }
 
 impl FromStr for FileType {
@@ -204,10 +259,26 @@ pub struct FileInfo {
     pub extension: Option<String>,
     #[serde(rename = "fileType")]
     pub file_type: FileType,
+    #[ts(type = "number")]
     pub size: u64,
+    /* unix timestamps in seconds */
     #[serde(rename = "lastModified")]
-    pub last_modified: Option<u64>,
-    pub created: Option<u64>,
+    #[ts(type = "number | null")]
+    pub last_modified: Option<i64>,
+    #[ts(type = "number | null")]
+    pub created: Option<i64>,
+}
+
+//* Seconds since the unix epoch, negative for times before it */
+pub fn unix_timestamp(time: SystemTime) -> i64 {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(duration) => duration.as_secs() as i64,
+        Err(e) => -(e.duration().as_secs() as i64),
+    }
+}
+
+fn to_iso_8601(timestamp: i64) -> Option<String> {
+    DateTime::<Utc>::from_timestamp(timestamp, 0).map(|date| date.to_rfc3339())
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -225,24 +296,17 @@ impl FromStr for FileInfo {
                 file_name,
                 path: filepath.to_string(),
                 extension: extension.clone(),
-                file_type: if extension.clone().is_some() {
-                    FileType::from_extension(extension.as_deref())
-                } else if metadata.is_dir() {
+                // app bundles are directories too, but they're opened as a whole
+                file_type: if metadata.is_dir() && extension.as_deref() != Some("app") {
                     FileType::Directory
+                } else if extension.is_some() {
+                    FileType::from_extension(extension.as_deref())
                 } else {
                     FileType::File
                 },
                 size: metadata.len(),
-                last_modified: metadata
-                    .modified()
-                    .ok()
-                    .map(|date| date.elapsed().ok())
-                    .and_then(|date| date.map(|d| d.as_secs())),
-                created: metadata
-                    .created()
-                    .ok()
-                    .map(|date| date.elapsed().ok())
-                    .and_then(|date| date.map(|d| d.as_secs())),
+                last_modified: metadata.modified().ok().map(unix_timestamp),
+                created: metadata.created().ok().map(unix_timestamp),
             })
         } else {
             Err(ParsePathError)
@@ -116,3 +116,25 @@ pub fn config_filepath() -> Option<PathBuf> {
         dir
     })
 }
+
+pub fn get_scripts_path() -> Option<PathBuf> {
+    config_dir().and_then(|mut dir| {
+        dir.push("scripts");
+        if let Err(e) = fs::create_dir_all(&dir) {
+            eprintln!("Failed to create directory: {}", e);
+            return None;
+        }
+        Some(dir)
+    })
+}
+
+pub fn get_plugins_path() -> Option<PathBuf> {
+    config_dir().and_then(|mut dir| {
+        dir.push("plugins");
+        if let Err(e) = fs::create_dir_all(&dir) {
+            eprintln!("Failed to create directory: {}", e);
+            return None;
+        }
+        Some(dir)
+    })
+}
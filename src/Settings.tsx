@@ -1,19 +1,202 @@
-import { useCallback } from 'react';
+import { useCallback, useEffect, useState } from 'react';
+import { listen } from '@tauri-apps/api/event';
 import { useInputHandler } from './hooks/useInputHandler';
-import { toggle_settings_window } from './invocations';
+import {
+  get_settings,
+  set_launch_shortcut,
+  start_shortcut_recording,
+  stop_shortcut_recording,
+  toggle_settings_window,
+} from './invocations';
+import { LifecycleEvent } from './constants';
+import { ConfigError } from './types/ConfigError';
+import { KeybindingAction } from './types/KeybindingAction';
+import { SettingsState } from './types/SettingsState';
+
+const MODIFIER_CODES = /^(Control|Shift|Alt|Meta|OS)(Left|Right)?$/;
+
+// builds a shortcut in the format the global shortcut plugin parses, e.g. "Control+Shift+KeyK"
+function toShortcut(event: KeyboardEvent): string | null {
+  if (MODIFIER_CODES.test(event.code)) return null;
+  const modifiers = [
+    event.ctrlKey && 'Control',
+    event.altKey && 'Alt',
+    event.shiftKey && 'Shift',
+    event.metaKey && 'Super',
+  ].filter(Boolean);
+  return [...modifiers, event.code].join('+');
+}
+
+function describeAction(action: KeybindingAction): string {
+  switch (action.type) {
+    case 'OpenQueryMode':
+      return `Open in ${action.mode} mode`;
+    case 'RunScript':
+      return `Run the script ${action.script_name}`;
+    case 'OpenFile':
+      return `Open ${action.path}`;
+  }
+}
 
 export default function Settings() {
+  const [settings, setSettings] = useState<SettingsState | null>(null);
+  const [recording, setRecording] = useState(false);
+  const [shortcutError, setShortcutError] = useState<string | null>(null);
+
+  const stopRecording = useCallback(async () => {
+    setRecording(false);
+    await stop_shortcut_recording();
+  }, []);
+
   useInputHandler(
-    useCallback(async (event) => {
-      if (!event) return;
-      const { key, shiftKey, ctrlKey, metaKey, altKey } = event;
-      switch (key) {
-        case 'Escape': {
-          await toggle_settings_window();
+    useCallback(
+      async (event) => {
+        if (!event) return;
+        if (recording) {
+          event.preventDefault();
+          if (event.key === 'Escape') {
+            await stopRecording();
+            return;
+          }
+          const shortcut = toShortcut(event);
+          if (!shortcut) return;
+          try {
+            setSettings(await set_launch_shortcut(shortcut));
+            setShortcutError(null);
+          } catch (error) {
+            setShortcutError((error as ConfigError).message);
+          }
+          await stopRecording();
+          return;
+        }
+        const { key } = event;
+        switch (key) {
+          case 'Escape': {
+            await toggle_settings_window();
+          }
         }
-      }
-    }, []),
+      },
+      [recording, stopRecording],
+    ),
   );
 
-  return <>Settings!</>;
+  useEffect(() => {
+    get_settings().then(setSettings);
+    const unlisten = listen(LifecycleEvent.SettingsWindowShown, () => {
+      get_settings().then(setSettings);
+    });
+    const unlistenChanged = listen<SettingsState>(
+      LifecycleEvent.SettingsChanged,
+      (event) => setSettings(event.payload),
+    );
+    return () => {
+      unlisten.then((f) => f());
+      unlistenChanged.then((f) => f());
+    };
+  }, []);
+
+  const startRecording = async () => {
+    setShortcutError(null);
+    await start_shortcut_recording();
+    setRecording(true);
+  };
+
+  return (
+    <>
+      Settings!
+      {settings && (
+        <div className="settings-shortcut">
+          Launch shortcut:{' '}
+          <code>
+            {recording ? 'Press the new shortcut…' : settings.config.launch_shortcut}
+          </code>
+          {!recording && <button onClick={startRecording}>Record</button>}
+          {shortcutError && <p className="settings-error">{shortcutError}</p>}
+        </div>
+      )}
+      {settings && settings.config.keybindings.length > 0 && (
+        <table className="settings-keybindings">
+          <thead>
+            <tr>
+              <th>Shortcut</th>
+              <th>Action</th>
+            </tr>
+          </thead>
+          <tbody>
+            {settings.config.keybindings.map(({ shortcut, action }, i) => (
+              <tr key={`${i}-${shortcut}`}>
+                <td>
+                  <code>{shortcut}</code>
+                </td>
+                <td>{describeAction(action)}</td>
+              </tr>
+            ))}
+          </tbody>
+        </table>
+      )}
+      {settings && settings.config.mode_prefixes.length > 0 && (
+        <table className="settings-keybindings">
+          <thead>
+            <tr>
+              <th>Prefix</th>
+              <th>Mode</th>
+            </tr>
+          </thead>
+          <tbody>
+            {settings.config.mode_prefixes.map(({ prefix, mode }, i) => (
+              <tr key={`${i}-${prefix}`}>
+                <td>
+                  <code>{prefix}</code>
+                </td>
+                <td>{mode}</td>
+              </tr>
+            ))}
+          </tbody>
+        </table>
+      )}
+      {settings && Object.keys(settings.config.providers).length > 0 && (
+        <table className="settings-keybindings">
+          <thead>
+            <tr>
+              <th>Provider</th>
+              <th>Enabled</th>
+              <th>Priority</th>
+              <th>Timeout</th>
+              <th>Max results</th>
+            </tr>
+          </thead>
+          <tbody>
+            {Object.entries(settings.config.providers).map(
+              ([id, { enabled, priority, timeout_ms, max_results }]) => (
+                <tr key={id}>
+                  <td>
+                    <code>{id}</code>
+                  </td>
+                  <td>{enabled ? 'Yes' : 'No'}</td>
+                  <td>{priority}</td>
+                  <td>{timeout_ms}ms</td>
+                  <td>{max_results}</td>
+                </tr>
+              ),
+            )}
+          </tbody>
+        </table>
+      )}
+      {settings && (
+        <div className="settings-calculator">
+          Exchange rates from:{' '}
+          <code>{settings.config.calculator.exchange_rates_url}</code>
+        </div>
+      )}
+      {settings && settings.errors.length > 0 && (
+        <ul className="settings-errors">
+          {settings.errors.map((error) => (
+            <li key={`${error.field}: ${error.message}`}>
+              {error.field && <code>{error.field}</code>} {error.message}
+            </li>
+          ))}
+        </ul>
+      )}
+    </>
+  );
 }
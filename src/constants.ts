@@ -5,11 +5,13 @@ export const CHAT: QueryMode = 'Chat';
 export const SEARCH: QueryMode = 'Search';
 export const SCRIPTS: QueryMode = 'Scripts';
 export const BROWSER_HISTORY: QueryMode = 'BrowserHistory';
+export const CALCULATOR: QueryMode = 'Calculator';
 export const QUERY_MODES: QueryMode[] = [
   SEARCH,
   BROWSER_HISTORY,
   SCRIPTS,
   CHAT,
+  CALCULATOR,
 ] as const;
 
 export const NUMERIC = /\d+/;
@@ -22,4 +24,6 @@ export const LifecycleEvent = Object.freeze({
   MainWindowResized: 'MainWindowResized',
   Query: 'Query',
   QueryResult: 'QueryResult',
+  SettingsChanged: 'SettingsChanged',
+  SetQueryMode: 'SetQueryMode',
 }) satisfies Record<SFEvent, SFEvent>;
@@ -6,13 +6,13 @@ const actions = QUERY_MODES.map((str) => ({
 }));
 
 export function ActionSelector() {
-  const { mode } = useStore();
+  const { queryResult } = useStore();
   return (
     <div className="action-selector">
       {actions.map((item) => (
         <div
           key={item.title}
-          className={`${QUERY_MODES[mode] === item.title && 'active'} action `}
+          className={`${queryResult.mode === item.title && 'active'} action `}
         >
           <span>{item.title}</span>
         </div>
@@ -2,10 +2,12 @@ import {
   BROWSER_HISTORY_RESULT,
   CALCULATOR_RESULT,
   FILE_RESULT,
+  PLUGIN_RESULT,
   SCRIPT_RESULT,
 } from '../../types';
 import { getSelectedResult } from '../../react/reactStore';
 import { ResultPreview } from '../../types/ResultPreview';
+import { ResultDetails } from '../../types/ResultDetails';
 import ScriptDetails from '../../feature/details/ScriptDetails';
 import { FileDetails } from '../../feature/details/FileDetails';
 
@@ -28,6 +30,11 @@ const isScript = (
   preview: ResultPreview
 ): preview is Extract<ResultPreview, { type: typeof SCRIPT_RESULT }> => preview?.type === SCRIPT_RESULT
 
+const isPlugin = (
+  preview: ResultDetails,
+): preview is Extract<ResultDetails, { type: typeof PLUGIN_RESULT }> =>
+  preview?.type === PLUGIN_RESULT;
+
 export default function Details() {
   const result = getSelectedResult();
 
@@ -48,6 +55,9 @@ export default function Details() {
         />
       )}
       {isScript(preview) && <ScriptDetails {...preview} />}
+      {isPlugin(preview) && preview.description && (
+        <p className="plugin-description">{preview.description}</p>
+      )}
     </div>
   );
 }
@@ -13,7 +13,7 @@ export function FileDetails({ details }: FileDetailsProps) {
     return <>What the fuck</>
   }
 
-  const lastModified = details.lastModified ? new Date(parseInt(details.lastModified, 10) * 1000).toLocaleString() : null;
+  const lastModified = details.lastModified ? new Date(details.lastModified).toLocaleString() : null;
 
   const content = (() => {
     switch (details.fileType) {
@@ -33,6 +33,13 @@ export function FileDetails({ details }: FileDetailsProps) {
             {lastModified ? <div>last modified: {lastModified}</div> : null}
           </div>
         );
+      case 'Directory':
+        return (
+          <div>
+            <div>{details.path}</div>
+            {lastModified ? <div>last modified: {lastModified}</div> : null}
+          </div>
+        );
       case 'Pdf':
         return (
           <iframe
@@ -1,4 +1,6 @@
 import { invoke } from '@tauri-apps/api/core';
+import { AppConfig } from './types/AppConfig';
+import { SettingsState } from './types/SettingsState';
 
 export async function hide() {
   await invoke('hide_main_window');
@@ -21,3 +23,44 @@ export async function hide_settings_window() {
 export async function show_settings_window() {
   await invoke('show_settings_window');
 }
+
+export async function get_settings() {
+  return await invoke<SettingsState>('get_settings');
+}
+
+// rejects with the validation errors when any setting is invalid
+export async function save_settings(config: AppConfig) {
+  return await invoke<SettingsState>('save_settings', { config });
+}
+
+// rejects with a ConfigError when the shortcut is invalid or can't be registered
+export async function set_launch_shortcut(shortcut: string) {
+  return await invoke<SettingsState>('set_launch_shortcut', { shortcut });
+}
+
+export async function start_shortcut_recording() {
+  await invoke('start_shortcut_recording');
+}
+
+export async function stop_shortcut_recording() {
+  await invoke('stop_shortcut_recording');
+}
+
+export async function forget_history_domain(domain: string) {
+  return await invoke<number>('forget_history_domain', { domain });
+}
+
+// resolves once the plugin has run the action of one of its results
+export async function execute_plugin_action(plugin: string, value: string) {
+  await invoke('execute_plugin_action', { plugin, value });
+}
+
+// resolves to false when the tab couldn't be switched to and the url should be opened instead
+export async function activate_open_tab(url: string) {
+  return await invoke<boolean>('activate_open_tab', { url });
+}
+
+// makes a picked calculation available as `ans` and lists it in the calculator history
+export async function record_calculation(expression: string) {
+  await invoke('record_calculation', { expression });
+}
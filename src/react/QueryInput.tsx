@@ -5,7 +5,7 @@ export function QueryInput({
 }: {
   inputRef: React.RefObject<HTMLInputElement>;
 }) {
-  const { search_string, setSearchString } = useStore();
+  const { search_string, setSearchString, queryResult } = useStore();
   return (
     <div
       className="search-input-container draggable-area"
@@ -27,6 +27,27 @@ export function QueryInput({
           setSearchString(e.currentTarget.value);
         }}
       />
+      {queryResult.filters.length ? (
+        <div className="query-filters">
+          {queryResult.filters.map((filter) => (
+            <span
+              key={filter.text}
+              className={
+                filter.condition.type === 'Invalid'
+                  ? 'query-filter query-filter-invalid'
+                  : 'query-filter'
+              }
+              title={
+                filter.condition.type === 'Invalid'
+                  ? filter.condition.message
+                  : undefined
+              }
+            >
+              {filter.text}
+            </span>
+          ))}
+        </div>
+      ) : null}
     </div>
   );
 }
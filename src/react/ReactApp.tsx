@@ -1,12 +1,11 @@
 import { writeText } from '@tauri-apps/plugin-clipboard-manager';
-import { open } from '@tauri-apps/plugin-shell';
 import { getCurrentWebviewWindow } from '@tauri-apps/api/webviewWindow';
 import { useCallback, useRef } from 'react';
 import '../App.scss';
 import {
   BROWSER_HISTORY,
+  CALCULATOR,
   CHAT,
-  QUERY_MODES,
   SCRIPTS,
   SEARCH,
 } from '../constants';
@@ -63,10 +62,9 @@ function useInput(inputRef: React.RefObject<HTMLInputElement>) {
         if (!(metaKey || ctrlKey)) {
           return;
         }
-        const value = getSelectedResult(key)?.subheading;
-        if (value) {
-          await open(value);
-          await resetAndHide();
+        const result = getSelectedResult(key);
+        if (result) {
+          await openResult(result);
         }
       };
       const { key, shiftKey, ctrlKey, metaKey, altKey, location } = event;
@@ -123,7 +121,7 @@ function useInput(inputRef: React.RefObject<HTMLInputElement>) {
         case 'c':
         case 'C':
           if ((metaKey || ctrlKey) && shiftKey) {
-            const value = getSelectedResult()?.subheading;
+            const value = getSelectedResult()?.value;
             if (value) {
               await writeText(value);
             }
@@ -171,11 +169,13 @@ function App() {
 }
 
 function Results() {
-  const { mode } = useStore();
-  switch (QUERY_MODES[mode]) {
+  // a mode prefix can make this differ from the selected mode
+  const { queryResult } = useStore();
+  switch (queryResult.mode) {
     case SEARCH:
     case BROWSER_HISTORY:
     case SCRIPTS:
+    case CALCULATOR:
       return (
         <div className="detail-container">
           <ResultList />
@@ -1,16 +1,22 @@
+import { Fragment } from 'react';
 import SearchResult from './SearchResult';
 import { useStore } from './reactStore';
 
 export default function ResultList() {
   const { queryResult } = useStore();
+  // a header goes above the first result of every section
+  const sectionTitles = new Map(
+    queryResult.sections.map(({ start, title }) => [start, title]),
+  );
   return (
     <ul className="result-container grow">
       {queryResult.results.map((item, index) => (
-        <SearchResult
-          key={index + item.heading + item.subheading}
-          index={index}
-          {...item}
-        />
+        <Fragment key={index + item.heading + item.subheading}>
+          {sectionTitles.has(index) && (
+            <li className="result-section">{sectionTitles.get(index)}</li>
+          )}
+          <SearchResult index={index} {...item} />
+        </Fragment>
       ))}
     </ul>
   );
@@ -1,11 +1,23 @@
 import { create } from 'zustand';
 import { LifecycleEvent, NUMERIC, QUERY_MODES } from '../constants';
-import { hide } from '../invocations';
+import {
+  activate_open_tab,
+  execute_plugin_action,
+  hide,
+  record_calculation,
+} from '../invocations';
 import { emit, listen } from '@tauri-apps/api/event';
 import { QueryResult } from '../types/QueryResult';
 import { writeText } from '@tauri-apps/plugin-clipboard-manager';
-import { Nullable, FILE_RESULT, CALCULATOR_RESULT } from '../types';
-import { QueryResultItem } from '../types/QueryResultItem';
+import {
+  Nullable,
+  FILE_RESULT,
+  CALCULATOR_RESULT,
+  PLUGIN_RESULT,
+  OPEN_TAB_RESULT,
+} from '../types';
+import { ResultItem } from '../types/ResultItem';
+import { QueryMode } from '../types/QueryMode';
 import { open } from '@tauri-apps/plugin-shell';
 
 type ApplicationState = {
@@ -36,7 +48,7 @@ const defaultState: ApplicationState = {
   touched: false,
   mode: 0,
   cursor: 0,
-  queryResult: { results: [] },
+  queryResult: { results: [], filters: [], mode: QUERY_MODES[0], sections: [] },
 };
 
 export const useStore = create<Store>()((set, get) => {
@@ -47,6 +59,18 @@ export const useStore = create<Store>()((set, get) => {
       }
       set({ queryResult: data.payload });
     });
+    // sent when a keybinding opens swordfish in a specific mode
+    listen<QueryMode>(LifecycleEvent.SetQueryMode, ({ payload }) => {
+      const mode = QUERY_MODES.indexOf(payload);
+      if (mode < 0) {
+        return;
+      }
+      set({ mode });
+      emit(LifecycleEvent.Query, {
+        mode: payload,
+        search_string: get().search_string,
+      });
+    });
     listen(LifecycleEvent.MainWindowHidden, () => {
       console.log('main window hidden');
       resetAndHide();
@@ -63,14 +87,20 @@ export const useStore = create<Store>()((set, get) => {
 
   async function resetAndHide() {
     await hide();
-    set(({ prev_search, search_string }) => ({
+    set(({ prev_search, search_string, mode }) => ({
       search_string: '',
       touched: false,
       prev_search: search_string
         ? [...prev_search, search_string]
         : prev_search,
       prev_search_index: 0,
-      queryResult: { inline_result: '', results: [] },
+      queryResult: {
+        inline_result: '',
+        results: [],
+        filters: [],
+        mode: QUERY_MODES[mode],
+        sections: [],
+      },
       cursor: 0,
     }));
   }
@@ -189,7 +219,7 @@ function scrollToCursorPosition(cursor: number) {
   }
 }
 
-export async function openResult(result: Nullable<QueryResultItem>) {
+export async function openResult(result: Nullable<ResultItem>) {
   const { resetAndHide } = useStore.getState();
   if (!result) {
     console.log('selection is invalid!?', result);
@@ -197,17 +227,38 @@ export async function openResult(result: Nullable<QueryResultItem>) {
   }
   switch (result.type) {
     case FILE_RESULT: {
-      await open(result.subheading);
+      await open(result.value);
       await resetAndHide();
       break;
     }
     case CALCULATOR_RESULT: {
       await writeText(result.heading);
+      if (result.details?.type === 'Calculator') {
+        await record_calculation(result.details.expression).catch(console.error);
+      }
+      await resetAndHide();
+      break;
+    }
+    case OPEN_TAB_RESULT: {
+      const activated = await activate_open_tab(result.value).catch((error) => {
+        console.error(error);
+        return false;
+      });
+      if (!activated) {
+        await open(result.value);
+      }
+      await resetAndHide();
+      break;
+    }
+    case PLUGIN_RESULT: {
+      if (result.details?.type === 'Plugin') {
+        await execute_plugin_action(result.details.plugin, result.value);
+      }
       await resetAndHide();
       break;
     }
     default: {
-      await open(result.subheading);
+      await open(result.value);
       await resetAndHide();
       break;
     }
@@ -1,4 +1,5 @@
 import { QueryResultType } from './types/QueryResultType';
+import { ResultType } from './types/ResultType';
 
 export type None = null | undefined;
 export type Nullable<T> = T | None;
@@ -8,3 +9,5 @@ export const BROWSER_HISTORY_RESULT= 'BrowserHistory' satisfies QueryResultType
 export const SCRIPT_RESULT= 'Script' satisfies QueryResultType ;
 export const ACTION_RESULT = 'Action' satisfies QueryResultType;
 export const CALCULATOR_RESULT = 'Calculator' satisfies QueryResultType;
+export const PLUGIN_RESULT = 'Plugin' satisfies ResultType;
+export const OPEN_TAB_RESULT = 'OpenTab' satisfies ResultType;
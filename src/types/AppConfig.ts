@@ -0,0 +1,9 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { BrowserHistoryConfig } from "./BrowserHistoryConfig";
+import type { CalculatorConfig } from "./CalculatorConfig";
+import type { Keybinding } from "./Keybinding";
+import type { ModePrefix } from "./ModePrefix";
+import type { ProviderSettings } from "./ProviderSettings";
+import type { SearchDirectory } from "./SearchDirectory";
+
+export type AppConfig = { version: number, launch_shortcut: string, search_directories: Array<SearchDirectory>, browser_history: BrowserHistoryConfig, keybindings: Array<Keybinding>, mode_prefixes: Array<ModePrefix>, providers: Record<string, ProviderSettings>, calculator: CalculatorConfig, };
@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { Browser } from "./Browser";
+
+export type BrowserHistoryConfig = { disabled_browsers: Array<Browser>, allowed_domains: Array<string>, denied_domains: Array<string>, retention_days: number, fetch_missing_favicons: boolean, };
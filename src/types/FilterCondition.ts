@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { FileType } from "./FileType";
+
+export type FilterCondition = { "type": "Extension", extensions: Array<string>, } | { "type": "FileType", fileTypes: Array<FileType>, } | { "type": "Path", path: string, } | { "type": "Modified", after: number | null, before: number | null, } | { "type": "Created", after: number | null, before: number | null, } | { "type": "Size", min: number | null, max: number | null, } | { "type": "Invalid", message: string, };
@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { QueryMode } from "./QueryMode";
+
+export type KeybindingAction = { "type": "OpenQueryMode", mode: QueryMode, } | { "type": "RunScript", script_name: string, } | { "type": "OpenFile", path: string, };
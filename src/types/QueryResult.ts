@@ -1,4 +1,7 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { QueryFilter } from "./QueryFilter";
+import type { QueryMode } from "./QueryMode";
 import type { ResultItem } from "./ResultItem";
+import type { ResultSection } from "./ResultSection";
 
-export type QueryResult = { results: Array<ResultItem>, };
+export type QueryResult = { results: Array<ResultItem>, filters: Array<QueryFilter>, mode: QueryMode, sections: Array<ResultSection>, };
@@ -28,4 +28,4 @@ export type ResultPreview =
       content: string;
       parsedContent: string | null;
     }
-  | { type: 'Calculator'; parsedContent: string };
+  | { type: 'Calculator'; parsedContent: string; expression: string };
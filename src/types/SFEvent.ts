@@ -1,3 +1,3 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
 
-export type SFEvent = "MainWindowShown" | "MainWindowHidden" | "MainWindowResized" | "SettingsWindowShown" | "SettingsWindowHidden" | "Query" | "QueryResult" | "RunScript" | "ScriptResult";
+export type SFEvent = "MainWindowShown" | "MainWindowHidden" | "MainWindowResized" | "SettingsWindowShown" | "SettingsWindowHidden" | "Query" | "QueryResult" | "RunScript" | "ScriptResult" | "SettingsChanged" | "SetQueryMode";
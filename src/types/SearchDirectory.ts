@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { FileType } from "./FileType";
+
+export type SearchDirectory = { path: string, max_depth: number | null, include: Array<string>, exclude: Array<string>, respect_gitignore: boolean, include_hidden: boolean, file_types: Array<FileType>, };
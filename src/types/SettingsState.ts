@@ -0,0 +1,5 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { AppConfig } from "./AppConfig";
+import type { ConfigError } from "./ConfigError";
+
+export type SettingsState = { config: AppConfig, errors: Array<ConfigError>, };